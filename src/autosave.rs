@@ -0,0 +1,32 @@
+use calculator::{CalcError, CalculatorEngine};
+use std::path::PathBuf;
+
+// The REPL and the GUI restore the last session on startup and save it again after every input
+// that went through, so a crash loses at most what was being typed.
+const SESSION_FILE: &str = ".calc_session.json";
+
+// a file in the home directory, or in the working directory when there is no home
+pub fn home_file(name: &str) -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+pub fn path() -> PathBuf {
+    home_file(SESSION_FILE)
+}
+
+// false when there was no session to restore yet
+pub fn restore(engine: &mut CalculatorEngine) -> Result<bool, CalcError> {
+    let path = path();
+    if !path.exists() {
+        return Ok(false);
+    }
+    engine.restore_session(&path)?;
+    Ok(true)
+}
+
+pub fn save(engine: &CalculatorEngine) -> Result<(), CalcError> {
+    engine.save_session(path())
+}
@@ -0,0 +1,15 @@
+#[cfg(feature = "repl")]
+pub mod repl;
+pub mod script;
+
+#[cfg(feature = "repl")]
+pub use repl::Repl;
+
+// how many parentheses are still open, statements with open ones continue on the next line
+pub fn open_parentheses(input: &str) -> i32 {
+    input.chars().fold(0, |depth, c| match c {
+        '(' => depth + 1,
+        ')' => depth - 1,
+        _ => depth,
+    })
+}
@@ -0,0 +1,186 @@
+use crate::autosave;
+use crate::cli::open_parentheses;
+use calculator::{CalculatorEngine, Value};
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+const HISTORY_FILE: &str = ".calc_history";
+
+const HELP: &str = "\
+Enter an expression to evaluate it, statements can be separated with ';'.
+  x = 2*y + 1        assign a variable
+  ans * 2, $3 + 1    use the last or the third result, _ is the same as ans
+  y := x / 2         bind a variable, it follows changes of x
+  f(x, y) = x^2 + y  define a function
+  diff(f(x, y), x)   differentiate
+  solve(x^2 == 4, x) solve an equation
+  a < b, a >= b      compare two numbers
+  table(x^2, x, 0, 1, 0.1)  values over a range, add y, 1, 5, 1 for a second variable
+  save \"s.json\"      save the session to a file, load \"s.json\" brings it back
+  undo, redo         take back the last input that changed something, or do it again
+  vars               list variables and functions, inspect x shows where x comes from and what uses it
+  unset x y          remove variables or functions, rename x y gives x a new name everywhere
+  reset              start over without variables, functions or history
+A line ending in '\\' or with unclosed parentheses continues on the next line.
+When a statement fails, the ones before it on the same line are undone too.
+The session is saved after every input and restored on the next start.
+
+Commands:
+  :vars     list variables and functions
+  :history  list earlier results with their $n
+  :clear    forget all variables and functions
+  :help     show this message
+  :quit     leave (Ctrl+D works too)";
+
+pub struct Repl {
+    engine: CalculatorEngine,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self {
+            engine: CalculatorEngine::new(),
+        }
+    }
+
+    pub fn run(mut self) {
+        let mut editor = match DefaultEditor::new() {
+            Ok(editor) => editor,
+            Err(err) => {
+                eprintln!("Error: could not start line editor: {}", err);
+                return;
+            }
+        };
+        let history = autosave::home_file(HISTORY_FILE);
+        // a missing history file on the first start is expected
+        let _ = editor.load_history(&history);
+
+        println!("Calculator REPL, :help for help");
+        match autosave::restore(&mut self.engine) {
+            Ok(true) => println!("Restored the last session from {}", autosave::path().display()),
+            Ok(false) => {}
+            Err(err) => eprintln!("Error: could not restore the last session: {}", err),
+        }
+        while let Some(input) = read_statement(&mut editor) {
+            let trimmed = input.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let _ = editor.add_history_entry(trimmed);
+            if !self.handle(trimmed) {
+                break;
+            }
+        }
+
+        if let Err(err) = editor.save_history(&history) {
+            eprintln!("Error: could not save history to {}: {}", history.display(), err);
+        }
+    }
+
+    // returns false once the user asked to leave
+    fn handle(&mut self, input: &str) -> bool {
+        match input {
+            ":quit" | ":q" | ":exit" => return false,
+            ":help" => println!("{}", HELP),
+            ":clear" => {
+                self.engine.clear();
+                println!("Cleared all variables and functions");
+                self.autosave();
+            }
+            ":vars" => self.print_vars(),
+            ":history" => self.print_history(),
+            command if command.starts_with(':') => eprintln!("Unknown command {}, try :help", command),
+            _ => match self.engine.evaluate_with_diagnostics(input) {
+                Ok(values) => {
+                    // the results are the newest history entries, numbered so they can be used as $n
+                    let format = self.engine.number_format();
+                    let first = self.engine.history().len().saturating_sub(values.len()) + 1;
+                    for (n, value) in (first..).zip(values) {
+                        match value {
+                            // messages from commands like vars, they can span several lines
+                            Value::Text(text) => println!("{}", text),
+                            value => println!("${} = {}", n, value.display_with(format)),
+                        }
+                    }
+                    let invalidated = self.engine.invalidated();
+                    if !invalidated.is_empty() {
+                        println!("updated {}", invalidated.join(", "));
+                    }
+                    self.autosave();
+                }
+                Err(err) => eprintln!("{}", err.render(input)),
+            },
+        }
+        true
+    }
+
+    fn autosave(&self) {
+        if let Err(err) = autosave::save(&self.engine) {
+            eprintln!("Error: could not save the session: {}", err);
+        }
+    }
+
+    fn print_history(&self) {
+        let format = self.engine.number_format();
+        let history = self.engine.history();
+        if history.is_empty() {
+            println!("No results yet");
+        }
+        for (n, entry) in (1..).zip(history) {
+            println!("${:<4} {} = {}", n, entry.input, entry.value.display_with(format));
+        }
+    }
+
+    fn print_vars(&self) {
+        let format = self.engine.number_format();
+        let variables = self.engine.variables();
+        let functions = self.engine.functions();
+        if variables.is_empty() && functions.is_empty() {
+            println!("No variables or functions defined");
+            return;
+        }
+        let width = variables.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        for (name, value) in variables {
+            match self.engine.binding(&name) {
+                Some(expr) => println!("{:width$} := {} = {}", name, expr.display_with(format), value.display_with(format), width = width),
+                None => println!("{:width$} = {}", name, value.display_with(format), width = width),
+            }
+        }
+        for (name, params, body) in functions {
+            println!("{}({}) = {}", name, params.join(", "), body.display_with(format));
+        }
+    }
+}
+
+// reads lines until the statement is complete, None on Ctrl+D
+fn read_statement(editor: &mut DefaultEditor) -> Option<String> {
+    let mut statement = String::new();
+    loop {
+        let prompt = if statement.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                if let Some(continued) = line.strip_suffix('\\') {
+                    statement.push_str(continued);
+                    statement.push(' ');
+                    continue;
+                }
+                statement.push_str(&line);
+                if open_parentheses(&statement) > 0 {
+                    statement.push(' ');
+                    continue;
+                }
+                return Some(statement);
+            }
+            // Ctrl+C drops the current input but keeps the session
+            Err(ReadlineError::Interrupted) => return Some(String::new()),
+            Err(ReadlineError::Eof) => return None,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                return None;
+            }
+        }
+    }
+}
+
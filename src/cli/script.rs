@@ -0,0 +1,144 @@
+use crate::cli::open_parentheses;
+use calculator::CalculatorEngine;
+use std::fs;
+use std::io::Write;
+
+const USAGE: &str = "usage: Rust-Calculator-Interpreter run <script.calc> [-o <output file>]";
+
+// one statement of a script together with where it starts in the file, both 1-based
+#[derive(Debug, PartialEq)]
+pub struct Statement {
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+    pieces: Vec<(usize, usize, usize)>, // (offset in text, line, column) where each source line starts
+}
+
+impl Statement {
+    // line and column in the file of a byte offset into text
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        match self.pieces.iter().rev().find(|(start, _, _)| *start <= offset) {
+            Some((start, line, column)) => {
+                let offset = offset.min(self.text.len());
+                (*line, column + self.text[*start..offset].chars().count())
+            }
+            None => (self.line, self.column),
+        }
+    }
+}
+
+// Scripts have one statement per line, '#' outside of quotes starts a comment that runs to the end
+// of the line.
+// A statement with unclosed parentheses or a trailing '\' continues on the next line.
+pub fn split_statements(source: &str) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    let mut pending: Option<Statement> = None;
+    for (index, raw) in source.lines().enumerate() {
+        let code = strip_comment(raw);
+        let (code, continued) = match code.trim_end().strip_suffix('\\') {
+            Some(code) => (code, true),
+            None => (code, false),
+        };
+        if code.trim().is_empty() && pending.is_none() {
+            continue;
+        }
+        let column = code.chars().take_while(|c| c.is_whitespace()).count() + 1;
+        let statement = pending.get_or_insert_with(|| Statement {
+            line: index + 1,
+            column,
+            text: String::new(),
+            pieces: Vec::new(),
+        });
+        statement.pieces.push((statement.text.len(), index + 1, column));
+        statement.text.push_str(code.trim());
+        statement.text.push(' ');
+        if !continued && open_parentheses(&statement.text) <= 0 {
+            let mut statement = pending.take().unwrap();
+            statement.text.truncate(statement.text.trim_end().len());
+            statements.push(statement);
+        }
+    }
+    // a script that ends inside parentheses still gets run so the error points at it
+    if let Some(mut statement) = pending {
+        statement.text.truncate(statement.text.trim_end().len());
+        statements.push(statement);
+    }
+    statements
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// entry point of the run subcommand, returns the process exit code
+pub fn run(args: &[String]) -> i32 {
+    let (path, output) = match args {
+        [path] => (path, None),
+        [path, flag, output] if flag == "-o" || flag == "--output" => (path, Some(output)),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("{}: error: {}", path, err);
+            return 1;
+        }
+    };
+
+    let mut engine = CalculatorEngine::new();
+    let mut lines: Vec<String> = Vec::new();
+    for statement in split_statements(&source) {
+        match engine.evaluate_with_diagnostics(&statement.text) {
+            Ok(values) => {
+                let format = engine.number_format();
+                lines.extend(values.iter().map(|v| v.display_with(format).to_string()));
+            }
+            Err(err) => {
+                // whatever ran before the error is still worth seeing
+                write_results(&lines, output.map(String::as_str));
+                let (line, column) = err.span.map_or((statement.line, statement.column), |span| statement.position(span.start));
+                eprintln!("{}:{}:{}: error: {}", path, line, column, err);
+                if let Some(underlined) = err.underline(&statement.text) {
+                    eprintln!("{}", underlined);
+                }
+                return 1;
+            }
+        }
+    }
+
+    if !write_results(&lines, output.map(String::as_str)) {
+        return 1;
+    }
+    0
+}
+
+fn write_results(lines: &[String], output: Option<&str>) -> bool {
+    let mut text = lines.join("\n");
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    match output {
+        Some(output) => match fs::write(output, text) {
+            Ok(()) => true,
+            Err(err) => {
+                eprintln!("{}: error: {}", output, err);
+                false
+            }
+        },
+        None => {
+            let _ = std::io::stdout().write_all(text.as_bytes());
+            true
+        }
+    }
+}
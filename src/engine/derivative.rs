@@ -0,0 +1,141 @@
+use crate::engine::CalcError;
+use crate::engine::Expr;
+use crate::engine::Number;
+use crate::engine::simplify::normalize;
+
+fn num(n: i64) -> Expr {
+    Expr::Number(Number::from(n))
+}
+
+fn call(name: &str, arg: Expr) -> Expr {
+    Expr::Call(name.to_string(), vec![arg])
+}
+
+fn pow(base: Expr, exp: Expr) -> Expr {
+    Expr::Pow(Box::new(base), Box::new(exp))
+}
+
+fn div(a: Expr, b: Expr) -> Expr {
+    Expr::Div(Box::new(a), Box::new(b))
+}
+
+pub fn depends_on(expr: &Expr, var: &str) -> bool {
+    match expr {
+        Expr::Number(_) => false,
+        Expr::Var(name) => name == var,
+        Expr::Add(terms) | Expr::Mul(terms) | Expr::Call(_, terms) | Expr::List(terms) => terms.iter().any(|t| depends_on(t, var)),
+        Expr::Sub(a, b) | Expr::Div(a, b) | Expr::Pow(a, b) | Expr::Equation(a, b) | Expr::Inequality(a, _, b) => depends_on(a, var) || depends_on(b, var),
+        Expr::Neg(a) => depends_on(a, var),
+    }
+}
+
+// derivative of expr with respect to var, run through normalize
+pub fn differentiate(expr: &Expr, var: &str) -> Result<Expr, CalcError> {
+    Ok(normalize(derive(expr, var)?))
+}
+
+fn derive(expr: &Expr, var: &str) -> Result<Expr, CalcError> {
+    if !depends_on(expr, var) {
+        return Ok(num(0));
+    }
+
+    match expr {
+        Expr::Number(_) => Ok(num(0)),
+        Expr::Var(_) => Ok(num(1)),
+
+        Expr::Add(terms) => Ok(Expr::Add(
+            terms.iter().map(|t| derive(t, var)).collect::<Result<Vec<_>, _>>()?,
+        )),
+
+        Expr::Sub(a, b) => Ok(Expr::Sub(Box::new(derive(a, var)?), Box::new(derive(b, var)?))),
+
+        Expr::Neg(a) => Ok(Expr::Neg(Box::new(derive(a, var)?))),
+
+        Expr::List(items) => Ok(Expr::List(
+            items.iter().map(|t| derive(t, var)).collect::<Result<Vec<_>, _>>()?,
+        )),
+
+        Expr::Equation(_, _) => Err(CalcError::InvalidExpression("Cannot differentiate an equation".into())),
+        Expr::Inequality(_, _, _) => Err(CalcError::InvalidExpression("Cannot differentiate an inequality".into())),
+
+        // product rule over all factors: sum of (d t_i * every other factor)
+        Expr::Mul(factors) => {
+            let mut terms = Vec::new();
+            for (i, factor) in factors.iter().enumerate() {
+                if !depends_on(factor, var) {
+                    continue;
+                }
+                let mut product: Vec<Expr> = factors.clone();
+                product[i] = derive(factor, var)?;
+                terms.push(Expr::Mul(product));
+            }
+            Ok(Expr::Add(terms))
+        }
+
+        // quotient rule: (a' * b - a * b') / b^2
+        Expr::Div(a, b) => Ok(div(
+            Expr::Sub(
+                Box::new(Expr::Mul(vec![derive(a, var)?, (**b).clone()])),
+                Box::new(Expr::Mul(vec![(**a).clone(), derive(b, var)?])),
+            ),
+            pow((**b).clone(), num(2)),
+        )),
+
+        Expr::Pow(base, exp) => {
+            if !depends_on(exp, var) {
+                // power rule with chain: exp * base^(exp - 1) * base'
+                let lowered = match &**exp {
+                    Expr::Number(n) => Expr::Number(n.clone() - Number::one()),
+                    other => Expr::Sub(Box::new(other.clone()), Box::new(num(1))),
+                };
+                Ok(Expr::Mul(vec![(**exp).clone(), pow((**base).clone(), lowered), derive(base, var)?]))
+            } else if !depends_on(base, var) {
+                // a^g -> a^g * ln(a) * g'
+                Ok(Expr::Mul(vec![expr.clone(), call("ln", (**base).clone()), derive(exp, var)?]))
+            } else {
+                // f^g -> f^g * (g' * ln(f) + g * f' / f)
+                Ok(Expr::Mul(vec![
+                    expr.clone(),
+                    Expr::Add(vec![
+                        Expr::Mul(vec![derive(exp, var)?, call("ln", (**base).clone())]),
+                        div(Expr::Mul(vec![(**exp).clone(), derive(base, var)?]), (**base).clone()),
+                    ]),
+                ]))
+            }
+        }
+
+        Expr::Call(name, args) => {
+            let [arg] = args.as_slice() else {
+                return Err(CalcError::InvalidExpression(format!("Cannot differentiate {} with {} arguments", name, args.len())));
+            };
+            let outer = derive_function(name, arg)?;
+            // chain rule
+            Ok(Expr::Mul(vec![outer, derive(arg, var)?]))
+        }
+    }
+}
+
+// derivative of a one argument built-in, evaluated at arg
+fn derive_function(name: &str, arg: &Expr) -> Result<Expr, CalcError> {
+    let x = arg.clone();
+    let derivative = match name {
+        "sin" => call("cos", x),
+        "cos" => Expr::Neg(Box::new(call("sin", x))),
+        "tan" => div(num(1), pow(call("cos", x), num(2))),
+        "asin" => div(num(1), call("sqrt", Expr::Sub(Box::new(num(1)), Box::new(pow(x, num(2)))))),
+        "acos" => Expr::Neg(Box::new(div(num(1), call("sqrt", Expr::Sub(Box::new(num(1)), Box::new(pow(x, num(2)))))))),
+        "atan" => div(num(1), Expr::Add(vec![num(1), pow(x, num(2))])),
+        "sinh" => call("cosh", x),
+        "cosh" => call("sinh", x),
+        "tanh" => Expr::Sub(Box::new(num(1)), Box::new(pow(call("tanh", x), num(2)))),
+        "sqrt" => div(num(1), Expr::Mul(vec![num(2), call("sqrt", x)])),
+        "cbrt" => div(num(1), Expr::Mul(vec![num(3), pow(call("cbrt", x), num(2))])),
+        "exp" => call("exp", x),
+        "ln" => div(num(1), x),
+        "log" => div(num(1), Expr::Mul(vec![x, call("ln", num(10))])),
+        "log2" => div(num(1), Expr::Mul(vec![x, call("ln", num(2))])),
+        "abs" => div(x.clone(), call("abs", x)),
+        _ => return Err(CalcError::InvalidExpression(format!("Cannot differentiate {}", name))),
+    };
+    Ok(derivative)
+}
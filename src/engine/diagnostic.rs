@@ -0,0 +1,87 @@
+use crate::engine::CalcError;
+use std::fmt;
+
+// byte range of the input a token or expression came from, end is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    // smallest span covering both
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+// where every node of a parsed Expr came from, children in the same order as Expr::children
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprSpans {
+    pub span: Span,
+    pub children: Vec<ExprSpans>,
+}
+
+impl ExprSpans {
+    pub fn leaf(span: Span) -> Self {
+        Self { span, children: Vec::new() }
+    }
+}
+
+// an error together with the part of the input it is about, if that is known
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub error: CalcError,
+    pub span: Option<Span>,
+}
+
+impl CalcError {
+    pub fn at(self, span: Span) -> Diagnostic {
+        Diagnostic { error: self, span: Some(span) }
+    }
+}
+
+impl From<CalcError> for Diagnostic {
+    fn from(error: CalcError) -> Self {
+        Diagnostic { error, span: None }
+    }
+}
+
+impl Diagnostic {
+    // the line of source the error is on, underlined, followed by the message:
+    //   1 + 2/(3 - 3)
+    //       ^~~~~~~~~ Division by zero
+    pub fn render(&self, source: &str) -> String {
+        match self.underline(source) {
+            Some(underlined) => format!("{} {}", underlined, self.error),
+            None => self.error.to_string(),
+        }
+    }
+
+    // just the source line and the ^~~ below it, for callers that print the message elsewhere
+    pub fn underline(&self, source: &str) -> Option<String> {
+        let span = self.span.filter(|s| s.start <= source.len())?;
+        let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[span.start..].find('\n').map_or(source.len(), |i| span.start + i);
+        let line = &source[line_start..line_end];
+
+        // tabs are kept so the caret lines up however wide the terminal draws them
+        let padding: String = source[line_start..span.start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let end = span.end.clamp(span.start, line_end);
+        let width = source[span.start..end].chars().count().max(1);
+        Some(format!("{}\n{}^{}", line, padding, "~".repeat(width - 1)))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
@@ -1,129 +1,972 @@
 use std::cmp::PartialEq;
-use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use crate::engine::CalcError;
-use crate::engine::Token;
+use crate::engine::TokenKind;
 use crate::engine::eval_ast;
-use crate::engine::tokenize;
+use crate::engine::diagnostic::{Diagnostic, ExprSpans, Span};
+use crate::engine::evaluator::locate_error;
+use crate::engine::{parse, tokenize};
 use crate::engine::Expr;
-use crate::engine::parser::parse_to_ast;
+use crate::engine::{Curve, Graph, Implicit};
+use crate::engine::{Table, TableRange};
+use crate::engine::table::MAX_CELLS;
+use crate::engine::Number;
+use crate::engine::NumberFormat;
+use crate::engine::markup::{value_to_latex, value_to_mathml};
+use crate::engine::parser::{parse_statements, Statement};
+use crate::engine::functions::{is_history_name, is_reserved, is_valid_name, UserFunction};
+use crate::engine::session::Session;
+use std::fs;
+use std::path::Path;
 
 #[derive(Debug, Clone,PartialEq)]
+#[non_exhaustive]
 pub enum Value {
-    Number(f64),
+    Number(Number),
     Expression(Expr),
     Bool(bool),
+    Text(String), // what a command like save reports back
 }
+// one statement that went through and what it gave
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct HistoryEntry {
+    pub input: String,
+    pub value: Value,
+}
+
 pub struct CalculatorEngine {
     variables: HashMap<String, Expr>,
-    history: Vec<String>,
+    functions: HashMap<String, UserFunction>,
+    // defining expressions of variables assigned with :=, their values get recomputed from these
+    bindings: HashMap<String, Expr>,
+    // bound variables the last evaluate recomputed because something they depend on changed
+    invalidated: Vec<String>,
+    // statements that evaluated without an error, oldest first, $1 is the first one
+    history: Vec<HistoryEntry>,
+    // the history a reset or load in the running transaction replaced, put back if it fails
+    replaced_history: Option<Vec<HistoryEntry>>,
+    number_format: NumberFormat,
+    // what the session looked like before each change, with the input that made it
+    undo: Vec<(String, State)>,
+    redo: Vec<(String, State)>,
+}
+
+// the part of a session undo and redo go back and forth between, the history is not part of
+// it since undoing is something that happened too. Neither is the number format, it is a
+// setting of the front end that changes without an undo step of its own.
+#[derive(Clone, PartialEq)]
+struct State {
+    variables: HashMap<String, Expr>,
+    functions: HashMap<String, UserFunction>,
+    bindings: HashMap<String, Expr>,
+}
+
+// changes further back than this are forgotten
+const UNDO_LIMIT: usize = 100;
+
+impl Default for CalculatorEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CalculatorEngine {
     pub fn new() -> Self {
         Self {
             variables: HashMap::new(),
+            functions: HashMap::new(),
+            bindings: HashMap::new(),
+            invalidated: Vec::new(),
             history: Vec::new(),
+            replaced_history: None,
+            number_format: NumberFormat::default(),
+            undo: Vec::new(),
+            redo: Vec::new(),
         }
     }
 
+    pub fn number_format(&self) -> NumberFormat {
+        self.number_format
+    }
+
+    pub fn set_number_format(&mut self, format: NumberFormat) {
+        self.number_format = format;
+    }
+
+    // results of one input line as text, statements separated like they were typed
+    pub fn format_values(&self, values: &[Value]) -> String {
+        values
+            .iter()
+            .map(|v| v.display_with(self.number_format).to_string())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    // LaTeX source of the results, several statements are set apart with a \quad
+    pub fn latex_values(&self, values: &[Value]) -> String {
+        values
+            .iter()
+            .map(|v| value_to_latex(v, &self.number_format))
+            .collect::<Vec<_>>()
+            .join(";\\quad ")
+    }
+
+    // one <math> element per result
+    pub fn mathml_values(&self, values: &[Value]) -> String {
+        values
+            .iter()
+            .map(|v| value_to_mathml(v, &self.number_format))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
     pub fn evaluate(&mut self, input: &str) -> Result<Vec<Value>, CalcError> {
+        self.evaluate_with_diagnostics(input).map_err(|d| d.error)
+    }
+
+    // like evaluate, but errors keep the part of input they are about for Diagnostic::render
+    // An input is one step for undo, and when one of its statements fails the ones before it are
+    // rolled back as well.
+    pub fn evaluate_with_diagnostics(&mut self, input: &str) -> Result<Vec<Value>, Diagnostic> {
         let tokens = tokenize(input)?;
-        println!("tokens: {:?}", tokens);
-        let eof_split = tokens.split(|t| matches!(t,Token::EndOfFile));
-        let mut op_pos: Option<usize> = None;
-        let mut results: Vec<Value> = Vec::new();
-        for split in eof_split {
-            println!("split: {:?}", split); 
-            op_pos = split.iter().position(|t| { matches!(t,Token::Assign| Token::Equal | Token::PlusEqual| Token::MinusEqual| Token::StarEqual| Token::SlashEqual) });
-            println!("op_pos: {:?}", op_pos);
-            match op_pos {
-                //<Var> = <expression> or <expression> == <expression>, evaluates to Value and gets saved to variables or to a bool,
-                // Aded +=, -=, *= and /=
-                Some(_) => {
-                    let result = self.evaluate_equality_and_handle_assignment(&split, op_pos.unwrap())?;
-                    results.push(result)
-                },
-                //<expr>, evaluates to Value
-                None => {
-                    let parsed = parse_to_ast(&split.to_vec())?;
-                    println!("{:?}", parsed);
-                    let mut visited = HashSet::new();
-                    let result = eval_ast(&parsed, &self.variables, &mut visited)?;
-                    results.push(self.expr_to_value(&result))
+        let statements = parse_statements(&tokens)?;
+        self.invalidated.clear();
+
+        // undo and redo step through whole inputs, so they cannot be part of a bigger one
+        if let [(statement @ (Statement::Undo | Statement::Redo), span)] = statements.as_slice() {
+            let value = if *statement == Statement::Undo { self.undo() } else { self.redo() }.map_err(|e| e.at(*span))?;
+            self.history.push(HistoryEntry { input: input[span.start..span.end].to_string(), value: value.clone() });
+            return Ok(vec![value]);
+        }
+        if let Some((_, span)) = statements.iter().find(|(s, _)| matches!(s, Statement::Undo | Statement::Redo)) {
+            let command = &input[span.start..span.end];
+            return Err(CalcError::InvalidExpression(format!("{} has to be on its own", command)).at(*span));
+        }
+
+        self.transaction(input.trim(), |engine| {
+            let mut results: Vec<Value> = Vec::new();
+            for (statement, span) in statements {
+                let result = engine.execute(statement, span)?;
+                engine.history.push(HistoryEntry { input: input[span.start..span.end].to_string(), value: result.clone() });
+                results.push(result);
+            }
+            Ok(results)
+        })
+    }
+
+    fn state(&self) -> State {
+        State {
+            variables: self.variables.clone(),
+            functions: self.functions.clone(),
+            bindings: self.bindings.clone(),
+        }
+    }
+
+    fn set_state(&mut self, state: State) {
+        self.variables = state.variables;
+        self.functions = state.functions;
+        self.bindings = state.bindings;
+    }
+
+    // runs change as one undo step named label, or not at all if it fails
+    fn transaction<T, E>(&mut self, label: &str, change: impl FnOnce(&mut Self) -> Result<T, E>) -> Result<T, E> {
+        let before = self.state();
+        let format = self.number_format;
+        // a failed input only ever appends to the history, unless it replaced all of it
+        let history_len = self.history.len();
+        self.replaced_history = None;
+        let result = change(self);
+        let replaced = self.replaced_history.take();
+        match result {
+            Ok(result) => {
+                if self.state() != before {
+                    self.undo.push((label.to_string(), before));
+                    if self.undo.len() > UNDO_LIMIT {
+                        self.undo.remove(0);
+                    }
+                    self.redo.clear();
+                }
+                Ok(result)
+            }
+            Err(err) => {
+                self.set_state(before);
+                self.number_format = format;
+                match replaced {
+                    Some(history) => self.history = history,
+                    None => self.history.truncate(history_len),
                 }
+                self.invalidated.clear();
+                Err(err)
             }
         }
-        Ok(results)
     }
 
-    pub fn expr_to_value(&mut self, expr: &Expr) -> Value {
-        match expr {
-            Expr::Number(n) => Value::Number(*n),
-            _ => Value::Expression(expr.clone()),
+    // goes back to before the last change, reports which input that was
+    pub fn undo(&mut self) -> Result<Value, CalcError> {
+        let (label, state) = self.undo.pop().ok_or(CalcError::InvalidExpression("Nothing to undo".into()))?;
+        self.redo.push((label.clone(), self.state()));
+        self.set_state(state);
+        Ok(Value::Text(format!("Undid {}", label)))
+    }
+
+    pub fn redo(&mut self) -> Result<Value, CalcError> {
+        let (label, state) = self.redo.pop().ok_or(CalcError::InvalidExpression("Nothing to redo".into()))?;
+        self.undo.push((label.clone(), self.state()));
+        self.set_state(state);
+        Ok(Value::Text(format!("Redid {}", label)))
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    fn execute(&mut self, statement: Statement, span: Span) -> Result<Value, Diagnostic> {
+        let statement = self.resolve_history(statement, span)?;
+        match statement {
+            //<expression> == <expression> at the top level compares both sides to a bool
+            Statement::Expression(Expr::Equation(left, right), spans) => {
+                let left = self.eval_located(&(*left, child_spans(&spans, 0)), &HashSet::new())?;
+                let right = self.eval_located(&(*right, child_spans(&spans, 1)), &HashSet::new())?;
+                Ok(Value::Bool(left == right))
+            }
+            //<expression> < <expression> and the other orderings, only numbers can be ordered
+            Statement::Expression(Expr::Inequality(left, relation, right), spans) => {
+                let left = self.eval_located(&(*left, child_spans(&spans, 0)), &HashSet::new())?;
+                let right = self.eval_located(&(*right, child_spans(&spans, 1)), &HashSet::new())?;
+                match (&left, &right) {
+                    (Expr::Number(a), Expr::Number(b)) => Ok(Value::Bool(relation.holds(a, b))),
+                    (Expr::Number(_), other) | (other, _) => Err(CalcError::InvalidExpression(
+                        format!("Only numbers can be compared, {} is not one", other),
+                    ).at(spans.span)),
+                }
+            }
+            //table(expr, x, start, end, step) with an optional second range, shown as text
+            Statement::Expression(Expr::Call(name, args), spans) if name == "table" => {
+                let table = self.table_command(&args).map_err(|e| e.at(spans.span))?;
+                Ok(Value::Text(table.display_with(self.number_format)))
+            }
+            //<expr>, evaluates to Value
+            Statement::Expression(expr, spans) => {
+                let result = self.eval_located(&(expr, spans), &HashSet::new())?;
+                Ok(self.expr_to_value(&result))
+            }
+            //<Var> = <expression>, evaluates to Value and gets saved to variables, a name on the right
+            // means its current value so "x = x + 1" counts up
+            Statement::Assign { name, value, spans } => {
+                let span = spans.span;
+                let result = self.eval_located(&(value.clone(), spans), &HashSet::new())?;
+                let result = self.store(&name, &value, result, false).map_err(|e| e.at(span))?;
+                Ok(self.expr_to_value(&result))
+            }
+            //<Var> := <expression>, like = but the expression is kept and evaluated again when its variables change
+            Statement::Bind { name, value, spans } => {
+                let span = spans.span;
+                let result = self.eval_located(&(value.clone(), spans), &HashSet::new())?;
+                let result = self.store(&name, &value, result, true).map_err(|e| e.at(span))?;
+                Ok(self.expr_to_value(&result))
+            }
+            // +=, -=, *= and /=
+            Statement::Compound { name, op, value, spans, span } => {
+                // an error in the right side itself points there, one from combining points at the whole statement
+                self.eval_located(&(value.clone(), spans), &HashSet::new())?;
+                let variable_expr = Expr::Var(name.clone());
+                let combined: Expr = match op {
+                    TokenKind::PlusEqual => Expr::Add(vec![variable_expr, value]),
+                    TokenKind::MinusEqual => Expr::Sub(Box::new(variable_expr), Box::new(value)),
+                    TokenKind::StarEqual => Expr::Mul(vec![variable_expr, value]),
+                    TokenKind::SlashEqual => Expr::Div(Box::new(variable_expr), Box::new(value)),
+                    _ => return Err(CalcError::HowDidWeGetHere(format!("{} is not a compound assignment", op)).at(span)),
+                };
+                let result = eval_ast(&combined, &self.variables, &self.functions, &mut HashSet::new())
+                    .and_then(|result| self.store(&name, &combined, result, false))
+                    .map_err(|e| e.at(span))?;
+                Ok(self.expr_to_value(&result))
+            }
+            // save "path" and load "path"
+            Statement::Save { path, span } => {
+                self.save_session(&path).map_err(|e| e.at(span))?;
+                Ok(Value::Text(format!("Saved session to {}", path)))
+            }
+            Statement::Load { path, span } => {
+                read_session(&path).and_then(|json| self.replace_session(&json)).map_err(|e| e.at(span))?;
+                Ok(Value::Text(format!("Loaded session from {}", path)))
+            }
+            //<Func>(<Var>, ...) = <expression>, the body is stored as parsed so globals are looked up at call time
+            Statement::Define { name, name_span, params, body } => {
+                if is_reserved(&name) {
+                    return Err(CalcError::InvalidExpression(format!("Cannot redefine built-in function {}", name)).at(name_span));
+                }
+                // a call of itself, directly or through other functions, could only ever expand
+                // into another call
+                let mut path = vec![name.clone()];
+                if self.find_call_path(&name, body.calls(), &mut path, &mut HashSet::new()) {
+                    path.push(name);
+                    return Err(CalcError::CyclicDefinition(path).at(name_span));
+                }
+                self.functions.insert(name.clone(), UserFunction { params, body: body.clone() });
+                let updates = self.recompute_callers(&name).map_err(|e| e.at(name_span))?;
+                self.apply_updates(updates);
+                Ok(self.expr_to_value(&body))
+            }
+            Statement::Vars => Ok(Value::Text(self.describe_all())),
+            Statement::Inspect { name, span } => Ok(Value::Text(self.describe(&name).map_err(|e| e.at(span))?)),
+            Statement::Unset { names, span } => {
+                for name in &names {
+                    self.remove(name).map_err(|e| e.at(span))?;
+                }
+                Ok(Value::Text(format!("Removed {}", names.join(", "))))
+            }
+            Statement::Rename { from, to, span } => {
+                self.rename_variable(&from, &to).map_err(|e| e.at(span))?;
+                Ok(Value::Text(format!("Renamed {} to {}", from, to)))
+            }
+            Statement::Reset => {
+                self.reset_session();
+                Ok(Value::Text("Reset the session".to_string()))
+            }
+            // evaluate_with_diagnostics runs these itself, they never get here
+            Statement::Undo | Statement::Redo => Err(CalcError::HowDidWeGetHere("undo inside of an input".into()).at(span)),
+        }
+    }
+
+    // replaces ans, _ and $n with the results they refer to before anything is evaluated, so a
+    // binding or function body keeps the result it was written with
+    fn resolve_history(&self, statement: Statement, span: Span) -> Result<Statement, Diagnostic> {
+        let resolve = |expr: Expr, spans: &ExprSpans| -> Result<Expr, Diagnostic> {
+            let mut failed = None;
+            expr.replace_vars(&mut |name| {
+                self.history_value(name).inspect_err(|_| failed = Some(name.to_string()))
+            })
+            .map_err(|e| {
+                let span = failed.and_then(|name| var_span(&expr, spans, &name)).unwrap_or(spans.span);
+                e.at(span)
+            })
+        };
+        let assigned = |name: &str| match is_history_name(name) {
+            true => Err(CalcError::InvalidExpression(format!("{} refers to the history and cannot be assigned", name)).at(span)),
+            false => Ok(()),
+        };
+        Ok(match statement {
+            Statement::Expression(expr, spans) => Statement::Expression(resolve(expr, &spans)?, spans),
+            Statement::Assign { name, value, spans } => {
+                assigned(&name)?;
+                Statement::Assign { name, value: resolve(value, &spans)?, spans }
+            }
+            Statement::Bind { name, value, spans } => {
+                assigned(&name)?;
+                Statement::Bind { name, value: resolve(value, &spans)?, spans }
+            }
+            Statement::Compound { name, op, value, spans, span } => {
+                assigned(&name)?;
+                Statement::Compound { name, op, value: resolve(value, &spans)?, spans, span }
+            }
+            Statement::Define { name, name_span, params, body } => {
+                if let Some(param) = params.iter().find(|p| is_history_name(p)) {
+                    return Err(CalcError::InvalidExpression(format!("{} refers to the history and cannot be a parameter", param)).at(name_span));
+                }
+                let body = resolve(body, &ExprSpans::leaf(name_span))?;
+                Statement::Define { name, name_span, params, body }
+            }
+            other => other,
+        })
+    }
+
+    // what ans, _ or $n stand for, None for every other name
+    fn history_value(&self, name: &str) -> Result<Option<Expr>, CalcError> {
+        if !is_history_name(name) {
+            return Ok(None);
+        }
+        let entry = match name.strip_prefix('$') {
+            Some(index) => index.parse::<usize>().ok()
+                .and_then(|n| n.checked_sub(1))
+                .and_then(|i| self.history.get(i))
+                .ok_or(CalcError::InvalidExpression(format!("There is no result {} in the history", name)))?,
+            None => self.history.last()
+                .ok_or(CalcError::InvalidExpression(format!("There is no previous result for {}", name)))?,
+        };
+        match &entry.value {
+            Value::Number(n) => Ok(Some(Expr::Number(n.clone()))),
+            Value::Expression(expr) => Ok(Some(expr.clone())),
+            value => Err(CalcError::InvalidExpression(format!("{} is {}, not something to calculate with", name, value))),
         }
     }
 
-    fn evaluate_equality_and_handle_assignment(&mut self, tokens: &[Token], equality_pos: usize) -> Result<Value, CalcError> {
-        let left_expression = &tokens[0..equality_pos].to_vec();
-        let right_expression = &tokens[equality_pos+1..].to_vec();
-        match (left_expression.len(),right_expression.len()){
-            (0,_) => Err(CalcError::EmptyExpression),
-            (_,0) => Err(CalcError::EmptyExpression),
-            _ =>{
-                match tokens[equality_pos]{
-                    Token::Equal => {
-                        let parsed_left = parse_to_ast(left_expression)?;
-                        let parsed_right = parse_to_ast(right_expression)?;
-                        let mut visited = HashSet::new();
-                        let result_left = eval_ast(&parsed_left, &self.variables, &mut visited)?;
-                        println!("{:?}",visited);
-                        let result_right = eval_ast(&parsed_right, &self.variables, &mut visited)?;
-                        Ok(Value::Bool(result_left ==  result_right))
-                    },
-                    Token::Assign => {
-                        if let Token::Var(name) = &left_expression[0]{
-                            let parsed = parse_to_ast(right_expression)?;
-                            let mut visited = HashSet::new();
-                            visited.insert(name.clone());
-                            let result = eval_ast(&parsed, &self.variables, &mut visited)?;
-                            self.variables.insert(name.clone(), result.clone());
-                            Ok(self.expr_to_value(&result))
-                        }
-                        else {
-                            Err(CalcError::InvalidExpression("Cannot assign to a non variable expression".to_string()))
-                        }
-                    },
-                    Token::PlusEqual | Token::MinusEqual | Token::StarEqual | Token::SlashEqual => {
-                        if let Token::Var(name) = &left_expression[0] {
-                            let parsed_right = parse_to_ast(right_expression)?;
-                            let variable_expr = self.variables
-                                .get(name)
-                                .cloned()
-                                .unwrap_or(Expr::Var(name.clone()));
-
-                            // Combine: old + rhs
-
-                            let combined:Expr = match tokens[equality_pos]{
-                                Token::PlusEqual => Expr::Add(vec![variable_expr, parsed_right]),
-                                Token::MinusEqual => Expr::Sub(Box::new(variable_expr), Box::new(parsed_right)),
-                                Token::StarEqual => Expr::Mul(vec![variable_expr, parsed_right]),
-                                Token::SlashEqual => Expr::Div(Box::new(variable_expr), Box::new(parsed_right)),
-                                _ => Err(CalcError::HowDidWeGetHere("What".to_string()))?
-                            };
-                            let mut visited = HashSet::new();
-                            visited.insert(name.clone());
-                            let result = eval_ast(&combined, &self.variables,&mut visited)?;
-                            self.variables.insert(name.clone(), result.clone());
-                            Ok(self.expr_to_value(&result))
-                        }
-                        else{
-                            Err(CalcError::InvalidExpression("Cannot assign to a non variable expression".to_string()))
-                        }
+    // eval_ast that points errors at the subexpression that caused them
+    fn eval_located(&self, (expr, spans): &(Expr, ExprSpans), visited: &HashSet<String>) -> Result<Expr, Diagnostic> {
+        eval_ast(expr, &self.variables, &self.functions, &mut visited.clone()).map_err(|error| {
+            let span = locate_error(expr, spans, &self.variables, &self.functions, visited, &error);
+            error.at(span)
+        })
+    }
 
-                    }
-                    _ => Err(CalcError::HowDidWeGetHere("Another token in place of equality/assignment token".to_string()))
+    // evaluates an already parsed expression against the session without changing it
+    pub fn eval(&self, expr: &Expr) -> Result<Value, CalcError> {
+        let result = eval_ast(expr, &self.variables, &self.functions, &mut HashSet::new())?;
+        Ok(self.expr_to_value(&result))
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<Value> {
+        self.variables.get(name).map(|expr| self.expr_to_value(expr))
+    }
+
+    // stores value under name as if "name = value" was typed, so it gets evaluated first
+    pub fn set_variable(&mut self, name: &str, value: impl Into<Expr>) -> Result<Value, CalcError> {
+        if !is_valid_name(name) {
+            return Err(CalcError::InvalidExpression(format!("{} cannot be used as a variable name", name)));
+        }
+        let value = value.into();
+        self.invalidated.clear();
+        self.transaction(&format!("{} = {}", name, value), |engine| {
+            let result = eval_ast(&value, &engine.variables, &engine.functions, &mut HashSet::new())?;
+            let result = engine.store(name, &value, result, false)?;
+            Ok(engine.expr_to_value(&result))
+        })
+    }
+
+    // saves the evaluated result of an assignment, unless it still mentions the variable itself.
+    // Defined names are expanded during evaluation, so that only happens when the variable is
+    // undefined and reached again through itself or the variables it depends on. A live binding
+    // must not depend on itself even through defined variables, or recomputing would never end.
+    // Bindings that depend on the variable are recomputed, and nothing is changed if one of them fails.
+    fn store(&mut self, name: &str, assigned: &Expr, result: Expr, live: bool) -> Result<Expr, CalcError> {
+        if result.variables().contains(name)
+            || (live && self.find_path(name, assigned.variables(), &mut Vec::new(), &mut HashSet::new()))
+        {
+            return Err(CalcError::CyclicDefinition(self.cycle_path(name, assigned)));
+        }
+        let updates = self.recompute_dependents(name, Some(&result))?;
+        self.variables.insert(name.to_string(), result.clone());
+        if live {
+            self.bindings.insert(name.to_string(), assigned.clone());
+        } else {
+            self.bindings.remove(name);
+        }
+        self.apply_updates(updates);
+        Ok(result)
+    }
+
+    fn apply_updates(&mut self, updates: Vec<(String, Expr)>) {
+        for (dependent, value) in updates {
+            self.variables.insert(dependent.clone(), value);
+            if !self.invalidated.contains(&dependent) {
+                self.invalidated.push(dependent);
+            }
+        }
+    }
+
+    // new values of the bindings that depend on changed, directly or through other bindings,
+    // in an order where every binding comes after the ones it depends on. A value of None means
+    // changed is going away.
+    fn recompute_dependents(&self, changed: &str, value: Option<&Expr>) -> Result<Vec<(String, Expr)>, CalcError> {
+        let mut scratch = self.variables.clone();
+        match value {
+            Some(value) => scratch.insert(changed.to_string(), value.clone()),
+            None => scratch.remove(changed),
+        };
+        self.recompute(|name, expr| name != changed && expr.variables().contains(changed), scratch)
+    }
+
+    // new values of the bindings that call function, directly or through other functions, and
+    // of the bindings depending on those
+    fn recompute_callers(&self, function: &str) -> Result<Vec<(String, Expr)>, CalcError> {
+        let calls = |_: &str, expr: &Expr| self.find_call_path(function, expr.calls(), &mut Vec::new(), &mut HashSet::new());
+        self.recompute(calls, self.variables.clone())
+    }
+
+    // the bindings changed picks out and all that depend on them, evaluated over scratch
+    fn recompute(&self, changed: impl Fn(&str, &Expr) -> bool, mut scratch: HashMap<String, Expr>) -> Result<Vec<(String, Expr)>, CalcError> {
+        let mut affected: BTreeSet<&str> = self.bindings.iter().filter(|(name, expr)| changed(name, expr)).map(|(name, _)| name.as_str()).collect();
+        loop {
+            let before = affected.len();
+            for (name, expr) in &self.bindings {
+                if expr.variables().iter().any(|v| affected.contains(v.as_str())) {
+                    affected.insert(name);
                 }
             }
+            if affected.len() == before {
+                break;
+            }
+        }
+
+        let mut updates = Vec::new();
+        while !affected.is_empty() {
+            // there are no cycles, so some binding only waits on ones already done
+            let next = *affected
+                .iter()
+                .find(|name| self.bindings[**name].variables().iter().all(|v| !affected.contains(v.as_str())))
+                .ok_or(CalcError::HowDidWeGetHere("cycle between live bindings".into()))?;
+            affected.remove(next);
+            let result = eval_ast(&self.bindings[next], &scratch, &self.functions, &mut HashSet::new())?;
+            scratch.insert(next.to_string(), result.clone());
+            updates.push((next.to_string(), result));
+        }
+        Ok(updates)
+    }
+
+    // variables the value of name refers to, the edges of the dependency graph. For a live
+    // binding those come from its defining expression.
+    pub fn dependencies(&self, name: &str) -> BTreeSet<String> {
+        match self.bindings.get(name) {
+            Some(expr) => expr.variables(),
+            None => self.variables.get(name).map(Expr::variables).unwrap_or_default(),
         }
     }
-}
\ No newline at end of file
+
+    // variables whose value refers to name, the other direction of dependencies
+    pub fn dependents(&self, name: &str) -> BTreeSet<String> {
+        self.variables
+            .keys()
+            .filter(|other| self.dependencies(other).contains(name))
+            .cloned()
+            .collect()
+    }
+
+    // deletes a variable, or a function if there is no variable of that name. Bindings that used
+    // the variable are recomputed with it undefined.
+    pub fn unset(&mut self, name: &str) -> Result<(), CalcError> {
+        self.invalidated.clear();
+        self.transaction(&format!("unset {}", name), |engine| engine.remove(name))
+    }
+
+    // gives a variable a new name and rewrites every value, binding and function body that uses it
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), CalcError> {
+        self.transaction(&format!("rename {} {}", from, to), |engine| engine.rename_variable(from, to))
+    }
+
+    // back to a fresh session: no variables, functions or history and the default number format
+    pub fn reset(&mut self) {
+        let _ = self.transaction("reset", |engine| {
+            engine.reset_session();
+            Ok::<(), CalcError>(())
+        });
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), CalcError> {
+        if !self.variables.contains_key(name) {
+            return match self.functions.remove(name) {
+                Some(_) => Ok(()),
+                None => Err(CalcError::UndefinedVariable(name.to_string())),
+            };
+        }
+        let updates = self.recompute_dependents(name, None)?;
+        self.variables.remove(name);
+        self.bindings.remove(name);
+        for (dependent, value) in updates {
+            self.variables.insert(dependent.clone(), value);
+            if !self.invalidated.contains(&dependent) {
+                self.invalidated.push(dependent);
+            }
+        }
+        Ok(())
+    }
+
+    fn rename_variable(&mut self, from: &str, to: &str) -> Result<(), CalcError> {
+        if !self.variables.contains_key(from) {
+            return Err(CalcError::UndefinedVariable(from.to_string()));
+        }
+        if from == to {
+            return Ok(());
+        }
+        if !is_valid_name(to) {
+            return Err(CalcError::InvalidExpression(format!("{} cannot be used as a variable name", to)));
+        }
+        if self.variables.contains_key(to) {
+            return Err(CalcError::InvalidExpression(format!("{} already exists", to)));
+        }
+        // an expression that mentions the new name while it is undefined would suddenly get a value
+        let mentions = |expr: &Expr| expr.variables().contains(to);
+        let user = self.variables.iter().chain(&self.bindings).find(|(_, expr)| mentions(expr)).map(|(name, _)| name)
+            .or(self.functions.iter().find(|(_, f)| !f.params.iter().any(|p| p == to) && mentions(&f.body)).map(|(name, _)| name));
+        if let Some(user) = user {
+            return Err(CalcError::InvalidExpression(format!("Cannot rename {} to {}, {} already uses {}", from, to, user, to)));
+        }
+
+        let rename = |expr: &Expr| -> Expr {
+            expr.replace_vars(&mut |name| Ok::<_, Infallible>((name == from).then(|| Expr::Var(to.to_string()))))
+                .unwrap_or_else(|never| match never {})
+        };
+        for expr in self.variables.values_mut().chain(self.bindings.values_mut()) {
+            *expr = rename(expr);
+        }
+        for function in self.functions.values_mut() {
+            // inside a function with a parameter of that name it means the parameter
+            if !function.params.iter().any(|p| p == from) {
+                function.body = rename(&function.body);
+            }
+        }
+        if let Some(value) = self.variables.remove(from) {
+            self.variables.insert(to.to_string(), value);
+        }
+        if let Some(binding) = self.bindings.remove(from) {
+            self.bindings.insert(to.to_string(), binding);
+        }
+        Ok(())
+    }
+
+    fn reset_session(&mut self) {
+        self.variables.clear();
+        self.bindings.clear();
+        self.functions.clear();
+        self.replace_history(Vec::new());
+        self.number_format = NumberFormat::default();
+    }
+
+    fn replace_history(&mut self, history: Vec<HistoryEntry>) {
+        let old = std::mem::replace(&mut self.history, history);
+        self.replaced_history.get_or_insert(old);
+    }
+
+    // what the inspect command shows: the value or definition, then what it depends on and what uses it
+    fn describe(&self, name: &str) -> Result<String, CalcError> {
+        let format = self.number_format;
+        let Some(value) = self.variables.get(name) else {
+            return match self.functions.get(name) {
+                Some(f) => Ok(format!("{}({}) = {}", name, f.params.join(", "), f.body.display_with(format))),
+                None => Err(CalcError::UndefinedVariable(name.to_string())),
+            };
+        };
+        let mut lines = vec![self.variable_line(name, value)];
+        let list = |names: BTreeSet<String>| names.into_iter().collect::<Vec<_>>().join(", ");
+        let dependencies = self.dependencies(name);
+        if !dependencies.is_empty() {
+            lines.push(format!("depends on {}", list(dependencies)));
+        }
+        let dependents = self.dependents(name);
+        if !dependents.is_empty() {
+            lines.push(format!("used by {}", list(dependents)));
+        }
+        Ok(lines.join("\n"))
+    }
+
+    // name = value for vars and inspect. A stored value can mention variables that were only
+    // defined afterwards, then it is shown together with what it evaluates to now.
+    fn variable_line(&self, name: &str, stored: &Expr) -> String {
+        let format = self.number_format;
+        let stored = self.expr_to_value(stored);
+        let current = self.eval(&Expr::Var(name.to_string())).unwrap_or_else(|_| stored.clone());
+        match self.bindings.get(name) {
+            Some(expr) => format!("{} := {} = {}", name, expr.display_with(format), current.display_with(format)),
+            None if current != stored => format!("{} = {} = {}", name, stored.display_with(format), current.display_with(format)),
+            None => format!("{} = {}", name, current.display_with(format)),
+        }
+    }
+
+    // what the vars command shows, one variable or function per line
+    fn describe_all(&self) -> String {
+        let format = self.number_format;
+        let mut names: Vec<&String> = self.variables.keys().collect();
+        names.sort();
+        let mut lines: Vec<String> = names.into_iter().map(|name| self.variable_line(name, &self.variables[name])).collect();
+        lines.extend(self.functions().into_iter().map(|(name, params, body)| {
+            format!("{}({}) = {}", name, params.join(", "), body.display_with(format))
+        }));
+        if lines.is_empty() {
+            return "No variables or functions defined".to_string();
+        }
+        lines.join("\n")
+    }
+
+    // the expression a variable was bound to with :=, None for plain variables
+    pub fn binding(&self, name: &str) -> Option<&Expr> {
+        self.bindings.get(name)
+    }
+
+    // bound variables whose values changed during the last evaluate, in the order they were recomputed
+    pub fn invalidated(&self) -> &[String] {
+        &self.invalidated
+    }
+
+    // name -> ... -> name through the dependency graph, starting at what is being assigned
+    fn cycle_path(&self, name: &str, assigned: &Expr) -> Vec<String> {
+        let mut path = vec![name.to_string()];
+        let mut seen = HashSet::new();
+        if !self.find_path(name, assigned.variables(), &mut path, &mut seen) {
+            path.truncate(1);
+        }
+        path.push(name.to_string());
+        path
+    }
+
+    fn find_path(&self, target: &str, from: BTreeSet<String>, path: &mut Vec<String>, seen: &mut HashSet<String>) -> bool {
+        for dependency in from {
+            if dependency == target {
+                return true;
+            }
+            if !seen.insert(dependency.clone()) || !self.variables.contains_key(&dependency) {
+                continue;
+            }
+            path.push(dependency.clone());
+            if self.find_path(target, self.dependencies(&dependency), path, seen) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+
+    // find_path for functions, through the functions their bodies call
+    fn find_call_path(&self, target: &str, from: BTreeSet<String>, path: &mut Vec<String>, seen: &mut HashSet<String>) -> bool {
+        for callee in from {
+            if callee == target {
+                return true;
+            }
+            if !seen.insert(callee.clone()) || !self.functions.contains_key(&callee) {
+                continue;
+            }
+            path.push(callee.clone());
+            if self.find_call_path(target, self.functions[&callee].body.calls(), path, seen) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+
+    // prepares input for plotting against var: variables and functions of the session are filled
+    // in, var stays symbolic even if it has a value
+    pub fn graph(&self, input: &str, var: &str) -> Result<Graph, CalcError> {
+        let expr = parse(input).map_err(|d| d.error)?;
+        let mut visited = HashSet::from([var.to_string()]);
+        let expr = eval_ast(&expr, &self.variables, &self.functions, &mut visited)?;
+        Graph::new(input.trim(), var, expr)
+    }
+
+    // the curve (x(t), y(t)) for the parameter var
+    pub fn parametric(&self, x: &str, y: &str, var: &str) -> Result<Curve, CalcError> {
+        let label = format!("({}, {})", x.trim(), y.trim());
+        Ok(Curve::parametric(&label, self.graph(x, var)?, self.graph(y, var)?))
+    }
+
+    // the curve r(var) in polar coordinates, var being the angle
+    pub fn polar(&self, r: &str, var: &str) -> Result<Curve, CalcError> {
+        let label = format!("r = {}", r.trim());
+        Ok(Curve::polar(&label, self.graph(r, var)?))
+    }
+
+    // prepares an equation like "x^2 + y^2 == 25" or an inequality like "y < 2*x + 1" for
+    // plotting over x and y
+    pub fn implicit(&self, input: &str) -> Result<Implicit, CalcError> {
+        let (left, relation, right) = match parse(input).map_err(|d| d.error)? {
+            Expr::Equation(left, right) => (left, None, right),
+            Expr::Inequality(left, relation, right) => (left, Some(relation), right),
+            _ => return Err(CalcError::InvalidExpression("Expected an equation like x^2 + y^2 == 25 or an inequality like y < 2*x + 1".into())),
+        };
+        let mut visited = HashSet::from(["x".to_string(), "y".to_string()]);
+        let left = eval_ast(&left, &self.variables, &self.functions, &mut visited)?;
+        let right = eval_ast(&right, &self.variables, &self.functions, &mut visited)?;
+        Implicit::new(input.trim(), left, relation, right)
+    }
+
+    // values of expr for every value of the range, or every pair of values of two ranges. Other
+    // variables have their values from the session, the ones of the ranges are replaced.
+    pub fn table(&self, expr: &Expr, ranges: &[TableRange]) -> Result<Table, CalcError> {
+        let cell = |assigned: &[(&str, &Number)]| -> Result<Value, CalcError> {
+            let mut scope = self.variables.clone();
+            for (var, value) in assigned {
+                scope.insert(var.to_string(), Expr::Number((*value).clone()));
+            }
+            let result = eval_ast(expr, &scope, &self.functions, &mut HashSet::new())?;
+            Ok(self.expr_to_value(&result))
+        };
+        match ranges {
+            [x] => {
+                let rows = x.values()?
+                    .into_iter()
+                    .map(|value| {
+                        let result = cell(&[(&x.var, &value)]);
+                        vec![Ok(Value::Number(value)), result]
+                    })
+                    .collect();
+                Ok(Table { headers: vec![x.var.clone(), expr.display_with(self.number_format).to_string()], rows })
+            }
+            [x, y] => {
+                if x.var == y.var {
+                    return Err(CalcError::InvalidExpression(format!("Both ranges are for {}", x.var)));
+                }
+                let (xs, ys) = (x.values()?, y.values()?);
+                if xs.len() * ys.len() > MAX_CELLS {
+                    return Err(CalcError::InvalidExpression(format!("A table can have at most {} values", MAX_CELLS)));
+                }
+                let mut headers = vec![format!("{} \\ {}", x.var, y.var)];
+                headers.extend(ys.iter().map(|value| value.to_string()));
+                let rows = xs
+                    .into_iter()
+                    .map(|a| {
+                        let mut row = vec![Ok(Value::Number(a.clone()))];
+                        row.extend(ys.iter().map(|b| cell(&[(&x.var, &a), (&y.var, b)])));
+                        row
+                    })
+                    .collect();
+                Ok(Table { headers, rows })
+            }
+            _ => Err(CalcError::InvalidExpression("A table runs over one or two variables".into())),
+        }
+    }
+
+    // the arguments of table(expr, x, start, end, step, [y, start, end, step])
+    fn table_command(&self, args: &[Expr]) -> Result<Table, CalcError> {
+        let usage = || CalcError::InvalidExpression("Expected table(expr, x, start, end, step), optionally followed by y, start, end, step".into());
+        let (expr, ranges) = args.split_first().ok_or_else(usage)?;
+        if ranges.len() != 4 && ranges.len() != 8 {
+            return Err(usage());
+        }
+        let ranges = ranges
+            .chunks(4)
+            .map(|range| {
+                let Expr::Var(var) = &range[0] else {
+                    return Err(usage());
+                };
+                let bound = |bound: &Expr| match eval_ast(bound, &self.variables, &self.functions, &mut HashSet::new())? {
+                    Expr::Number(n) => Ok(n),
+                    other => Err(CalcError::InvalidExpression(format!("The range of {} needs numbers, {} is not one", var, other))),
+                };
+                Ok(TableRange::new(var, bound(&range[1])?, bound(&range[2])?, bound(&range[3])?))
+            })
+            .collect::<Result<Vec<_>, CalcError>>()?;
+        self.table(expr, &ranges)
+    }
+
+    // variables sorted by name with their current values
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        let mut variables: Vec<(String, Value)> = self.variables
+            .iter()
+            .map(|(name, expr)| (name.clone(), self.expr_to_value(expr)))
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(&b.0));
+        variables
+    }
+
+    // user functions sorted by name as (name, parameters, body)
+    pub fn functions(&self) -> Vec<(String, Vec<String>, Value)> {
+        let mut functions: Vec<(String, Vec<String>, Value)> = self.functions
+            .iter()
+            .map(|(name, f)| (name.clone(), f.params.clone(), Value::Expression(f.body.clone())))
+            .collect();
+        functions.sort_by(|a, b| a.0.cmp(&b.0));
+        functions
+    }
+
+    // statements that evaluated without an error, oldest first, $1 is the first one
+    pub fn history(&self) -> &[HistoryEntry] {
+        &self.history
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    // the whole session as JSON, see session.rs for the layout
+    pub fn session_json(&self) -> Result<String, CalcError> {
+        Session {
+            variables: self.variables.clone(),
+            bindings: self.bindings.clone(),
+            functions: self.functions.clone(),
+            history: self.history.clone(),
+            number_format: self.number_format,
+        }
+        .to_json()
+    }
+
+    // replaces the session with one from session_json, nothing changes if json cannot be used.
+    // Loading can be undone like any other change.
+    pub fn load_session_json(&mut self, json: &str) -> Result<(), CalcError> {
+        self.transaction("load", |engine| engine.replace_session(json))
+    }
+
+    fn replace_session(&mut self, json: &str) -> Result<(), CalcError> {
+        let session = Session::from_json(json)?;
+        let mut restored = CalculatorEngine::new();
+        restored.variables = session.variables;
+        restored.functions = session.functions;
+        restored.bindings = session.bindings;
+        // a hand edited file could define a variable or function through itself
+        for name in restored.variables.keys() {
+            let mut path = vec![name.clone()];
+            if restored.find_path(name, restored.dependencies(name), &mut path, &mut HashSet::new()) {
+                path.push(name.clone());
+                return Err(CalcError::CyclicDefinition(path));
+            }
+        }
+        for (name, function) in &restored.functions {
+            let mut path = vec![name.clone()];
+            if restored.find_call_path(name, function.body.calls(), &mut path, &mut HashSet::new()) {
+                path.push(name.clone());
+                return Err(CalcError::CyclicDefinition(path));
+            }
+        }
+        self.variables = restored.variables;
+        self.functions = restored.functions;
+        self.bindings = restored.bindings;
+        self.replace_history(session.history);
+        self.number_format = session.number_format;
+        self.invalidated.clear();
+        Ok(())
+    }
+
+    pub fn save_session(&self, path: impl AsRef<Path>) -> Result<(), CalcError> {
+        let path = path.as_ref();
+        let json = self.session_json()?;
+        fs::write(path, json).map_err(|e| CalcError::FileError(format!("Could not write {}: {}", path.display(), e)))
+    }
+
+    pub fn load_session(&mut self, path: impl AsRef<Path>) -> Result<(), CalcError> {
+        let json = read_session(path.as_ref())?;
+        self.load_session_json(&json)
+    }
+
+    // like load_session but where the session starts from, so there is no step to undo that would
+    // take it away again. Undo and redo of anything before are dropped.
+    pub fn restore_session(&mut self, path: impl AsRef<Path>) -> Result<(), CalcError> {
+        let json = read_session(path.as_ref())?;
+        self.replace_session(&json)?;
+        self.replaced_history = None;
+        self.undo.clear();
+        self.redo.clear();
+        Ok(())
+    }
+
+    // forgets every variable and user function, undo brings them back
+    pub fn clear(&mut self) {
+        let _ = self.transaction("clear", |engine| {
+            engine.variables.clear();
+            engine.bindings.clear();
+            engine.functions.clear();
+            Ok::<(), CalcError>(())
+        });
+    }
+
+    pub fn expr_to_value(&self, expr: &Expr) -> Value {
+        match expr {
+            Expr::Number(n) => Value::Number(n.clone()),
+            _ => Value::Expression(expr.clone()),
+        }
+    }
+}
+
+// where in the input the first mention of a variable is
+fn var_span(expr: &Expr, spans: &ExprSpans, name: &str) -> Option<Span> {
+    if matches!(expr, Expr::Var(v) if v == name) {
+        return Some(spans.span);
+    }
+    expr.children()
+        .into_iter()
+        .zip(&spans.children)
+        .find_map(|(child, child_spans)| var_span(child, child_spans, name))
+}
+
+fn read_session(path: impl AsRef<Path>) -> Result<String, CalcError> {
+    let path = path.as_ref();
+    fs::read_to_string(path).map_err(|e| CalcError::FileError(format!("Could not read {}: {}", path.display(), e)))
+}
+
+// spans of the i-th operand, an equation that came in through ans or $n has only the span of
+// that name, which then stands for both sides
+fn child_spans(spans: &ExprSpans, i: usize) -> ExprSpans {
+    spans.children.get(i).cloned().unwrap_or_else(|| ExprSpans::leaf(spans.span))
+}
@@ -1,12 +1,47 @@
+use std::fmt;
+
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum CalcError {
     NotImplemented, // not implemented
     HowDidWeGetHere(String), // for situations that make no sense, mostly an inside joke from another project
-    InvalidExpression(String), // for invalid expressions like "!" in the base input during tokenization
+    InvalidExpression(String), // for invalid expressions like "$" in the base input during tokenization
     DivisionByZero, // obvious
     InvalidToken(String), // Getting this error should not be possible and that's why it exists
     //UnknownVariable(String), //(deprecated) for non assignment expressions, which contain an unknown variable
     MissingOperand, // When evaluating a rpn expression and cant find a operand for a operator
     EmptyExpression,
     TooManyOperands,
-}
\ No newline at end of file
+    UnknownFunction(String), // call to a name that is not in the function registry
+    ArityMismatch(String, usize, usize), // (function name, expected argument count, given argument count)
+    DomainError(String), // numeric function called outside of its domain, like sqrt(-1) or asin(2)
+    CyclicDefinition(Vec<String>), // assignment that would make a variable depend on itself or a function call itself, the path goes from it back to it
+    RecursionLimit(usize), // expression nested or expanded deeper than the limit, instead of overflowing the stack
+    FileError(String), // reading or writing a session file failed, or its contents make no sense
+    UndefinedVariable(String), // a command like unset or rename was given a name that has no value
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalcError::NotImplemented => write!(f, "Not implemented yet"),
+            CalcError::HowDidWeGetHere(what) => write!(f, "Internal error: {}", what),
+            CalcError::InvalidExpression(what) => write!(f, "Invalid expression: {}", what),
+            CalcError::DivisionByZero => write!(f, "Division by zero"),
+            CalcError::InvalidToken(token) => write!(f, "Invalid token: {}", token),
+            CalcError::MissingOperand => write!(f, "Missing operand"),
+            CalcError::EmptyExpression => write!(f, "Empty expression"),
+            CalcError::TooManyOperands => write!(f, "Too many operands"),
+            CalcError::UnknownFunction(name) => write!(f, "Unknown function: {}", name),
+            CalcError::ArityMismatch(name, expected, given) => {
+                let plural = if *expected == 1 { "" } else { "s" };
+                write!(f, "{} expects {} argument{}, got {}", name, expected, plural, given)
+            }
+            CalcError::DomainError(what) => write!(f, "Outside of the domain: {}", what),
+            CalcError::CyclicDefinition(path) => write!(f, "Cyclic definition: {}", path.join(" -> ")),
+            CalcError::RecursionLimit(limit) => write!(f, "Nested deeper than {} levels", limit),
+            CalcError::FileError(what) => write!(f, "{}", what),
+            CalcError::UndefinedVariable(name) => write!(f, "{} is not defined", name),
+        }
+    }
+}
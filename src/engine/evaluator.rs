@@ -1,15 +1,85 @@
 use crate::engine::CalcError;
+use std::cell::Cell;
 use std::collections::{HashMap, HashSet};
 use crate::engine::Expr;
+use crate::engine::Number;
+use crate::engine::functions::{call_builtin, UserFunction, SPECIAL_FORMS};
+use crate::engine::diagnostic::{ExprSpans, Span};
+use crate::engine::derivative::{depends_on, differentiate};
+use crate::engine::solver::solve;
+use crate::engine::simplify::normalize;
+
+// how deep evaluation (and parsing) may nest before giving up, low enough for the 2 MiB stack
+// of a spawned thread in a debug build
+pub const MAX_DEPTH: usize = 128;
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+// counts one level of eval_ast while alive, so every early return gives its level back
+struct DepthGuard;
+
+impl DepthGuard {
+    fn enter() -> Result<Self, CalcError> {
+        DEPTH.with(|depth| {
+            if depth.get() >= MAX_DEPTH {
+                return Err(CalcError::RecursionLimit(MAX_DEPTH));
+            }
+            depth.set(depth.get() + 1);
+            Ok(DepthGuard)
+        })
+    }
+}
 
-pub fn eval_ast(expr: &Expr, vars: &HashMap<String, Expr>, visited: &mut HashSet<String>) -> Result<Expr, CalcError>{
-    match eval(expr,vars,visited) {
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+pub fn eval_ast(expr: &Expr, vars: &HashMap<String, Expr>, funcs: &HashMap<String, UserFunction>, visited: &mut HashSet<String>) -> Result<Expr, CalcError>{
+    let _depth = DepthGuard::enter()?;
+    match eval(expr, vars, funcs, visited) {
         Ok(result) => Ok(normalize(result)),
         Err(e) => Err(e)
     }
 }
 
-pub fn eval(expr: &Expr, vars: &HashMap<String, Expr>, visited: &mut HashSet<String>) -> Result<Expr, CalcError> {
+// Expr carries no positions, so after a failed evaluation the culprit is found by evaluating
+// subexpressions on their own and following the one that fails the same way. The walk stops at
+// diff and solve, whose arguments do not mean the same thing outside of them.
+pub fn locate_error(expr: &Expr, spans: &ExprSpans, vars: &HashMap<String, Expr>, funcs: &HashMap<String, UserFunction>, visited: &HashSet<String>, error: &CalcError) -> Span {
+    if matches!(expr, Expr::Call(name, _) if SPECIAL_FORMS.contains(&name.as_str())) {
+        return spans.span;
+    }
+    for (child, child_spans) in expr.children().into_iter().zip(&spans.children) {
+        if let Err(e) = eval_ast(child, vars, funcs, &mut visited.clone())
+            && std::mem::discriminant(&e) == std::mem::discriminant(error)
+        {
+            return locate_error(child, child_spans, vars, funcs, visited, error);
+        }
+    }
+    spans.span
+}
+
+// a list, like the solutions of solve, is something to look at, not to calculate with
+fn operand(expr: Expr) -> Result<Expr, CalcError> {
+    match expr {
+        Expr::List(_) => Err(CalcError::InvalidExpression(format!("{} is a list and cannot be calculated with", expr))),
+        expr => Ok(expr),
+    }
+}
+
+fn divide(left: Expr, right: Expr) -> Result<Expr, CalcError> {
+    match (&left, &right) {
+        (_, Expr::Number(y)) if y.is_zero() => Err(CalcError::DivisionByZero),
+        (Expr::Number(x), Expr::Number(y)) => Ok(Expr::Number(x.checked_div(y)?)),
+        _ => Ok(normalize(Expr::Div(Box::new(left), Box::new(right)))),
+    }
+}
+
+pub fn eval(expr: &Expr, vars: &HashMap<String, Expr>, funcs: &HashMap<String, UserFunction>, visited: &mut HashSet<String>) -> Result<Expr, CalcError> {
     match expr {
         Expr::Number(_) => Ok(expr.clone()),
 
@@ -20,7 +90,7 @@ pub fn eval(expr: &Expr, vars: &HashMap<String, Expr>, visited: &mut HashSet<Str
 
             if let Some(val) = vars.get(name) {
                 visited.insert(name.clone());
-                let result = eval_ast(val, vars, visited)?;
+                let result = eval_ast(val, vars, funcs, visited)?;
                 visited.remove(name);
                 Ok(result)
             } else {
@@ -31,7 +101,7 @@ pub fn eval(expr: &Expr, vars: &HashMap<String, Expr>, visited: &mut HashSet<Str
         Expr::Add(terms) => {
             let evaluated = terms
                 .iter()
-                .map(|t| eval_ast(t, vars, visited))
+                .map(|t| eval_ast(t, vars, funcs, visited).and_then(operand))
                 .collect::<Result<Vec<_>, _>>()?;
 
             Ok(normalize(Expr::Add(evaluated)))
@@ -40,140 +110,165 @@ pub fn eval(expr: &Expr, vars: &HashMap<String, Expr>, visited: &mut HashSet<Str
         Expr::Mul(terms) => {
             let evaluated = terms
                 .iter()
-                .map(|t| eval_ast(t, vars, visited))
+                .map(|t| eval_ast(t, vars, funcs, visited).and_then(operand))
                 .collect::<Result<Vec<_>, _>>()?;
 
             Ok(normalize(Expr::Mul(evaluated)))
         }
 
         Expr::Neg(inner) => {
-            let val = eval_ast(inner, vars, visited)?;
+            let val = operand(eval_ast(inner, vars, funcs, visited)?)?;
             Ok(normalize(Expr::Neg(Box::new(val))))
         }
 
-        Expr::Sub(a, b) => {
-            let left = eval_ast(a, vars, visited)?;
-            let right = eval_ast(b, vars, visited)?;
-            Ok(normalize(Expr::Add(vec![left, Expr::Neg(Box::new(right))])))
-        }
-
-        Expr::Div(a, b) => {
-            let left = eval_ast(a, vars, visited)?;
-            let right = eval_ast(b, vars, visited)?;
-
-            match (&left, &right) {
-                (_, Expr::Number(0.0)) => Err(CalcError::DivisionByZero),
-                (Expr::Number(x), Expr::Number(y)) => Ok(Expr::Number(x / y)),
-                _ => Ok(normalize(Expr::Div(Box::new(left), Box::new(right)))),
+        // a - b - c and a / b / c nest to the left, the chain is walked in a loop so a long one
+        // does not count against MAX_DEPTH
+        Expr::Sub(..) | Expr::Div(..) => {
+            let mut steps = Vec::new();
+            let mut first = expr;
+            while let Expr::Sub(a, b) | Expr::Div(a, b) = first {
+                steps.push((first, b));
+                first = a;
+            }
+            let mut left = operand(eval_ast(first, vars, funcs, visited)?)?;
+            for (step, b) in steps.into_iter().rev() {
+                let right = operand(eval_ast(b, vars, funcs, visited)?)?;
+                left = match step {
+                    Expr::Sub(..) => normalize(Expr::Add(vec![left, Expr::Neg(Box::new(right))])),
+                    _ => divide(left, right)?,
+                };
             }
+            Ok(left)
         }
 
         Expr::Pow(a, b) => {
-            let base = eval_ast(a, vars, visited)?;
-            let exp = eval_ast(b, vars, visited)?;
+            let base = operand(eval_ast(a, vars, funcs, visited)?)?;
+            let exp = operand(eval_ast(b, vars, funcs, visited)?)?;
 
             match (&base, &exp) {
-                (Expr::Number(x), Expr::Number(y)) => Ok(Expr::Number(x.powf(*y))),
-                (_, Expr::Number(0.0)) => Ok(Expr::Number(1.0)),
-                (x, Expr::Number(1.0)) => Ok(x.clone()),
+                (Expr::Number(x), Expr::Number(y)) => Ok(Expr::Number(x.pow(y)?)),
+                (_, Expr::Number(y)) if y.is_zero() => Ok(Expr::Number(Number::one())),
+                (x, Expr::Number(y)) if y.is_one() => Ok(x.clone()),
                 _ => Ok(Expr::Pow(Box::new(base), Box::new(exp))),
             }
         }
-    }
-}
 
-pub fn normalize(expr: Expr) -> Expr {
-    match expr {
-        Expr::Add(terms) => {
-            let mut flat = Vec::new();
-            let mut sum = 0.0;
-
-            for t in terms {
-                match normalize(t) {
-                    Expr::Number(n) => sum += n,
-                    Expr::Add(inner) => flat.extend(inner),
-                    other => flat.push(other),
-                }
-            }
+        Expr::Call(name, args) if name == "diff" => eval_diff(args, vars, funcs, visited),
 
-            if sum != 0.0 {
-                flat.insert(0, Expr::Number(sum));
-            }
+        Expr::Call(name, args) if name == "solve" => eval_solve(args, vars, funcs, visited),
 
-            match flat.len() {
-                0 => Expr::Number(0.0),
-                1 => flat.pop().unwrap(),
-                _ => Expr::Add(flat),
-            }
-        }
+        Expr::Call(name, _) if name == "table" => Err(CalcError::InvalidExpression("table(...) has to be a statement of its own".into())),
 
-        Expr::Mul(terms) => {
-            let mut flat = Vec::new();
-            let mut num = 1.0;
-            let mut denom = 1.0;
-
-            for t in terms {
-                match normalize(t) {
-                    Expr::Number(n) => num *= n,
-
-                    Expr::Div(x, y) => {
-                        flat.push(*x);
-                        if let Expr::Number(d) = *y {
-                            denom *= d;
-                        } else {
-                            flat.push(Expr::Div(Box::new(Expr::Number(1.0)), y));
-                        }
-                    }
-
-                    Expr::Mul(inner) => flat.extend(inner),
-                    other => flat.push(other),
-                }
-            }
+        Expr::Equation(_, _) => Err(CalcError::InvalidExpression("An equation can only be used inside solve".into())),
 
-            if denom != 1.0 {
-                flat.push(Expr::Div(Box::new(Expr::Number(1.0)), Box::new(Expr::Number(denom))));
-            }
+        Expr::Inequality(_, _, _) => Err(CalcError::InvalidExpression("An inequality can only be checked or plotted, not calculated with".into())),
 
-            if num != 1.0 {
-                flat.insert(0, Expr::Number(num));
-            }
+        Expr::List(items) => {
+            let evaluated = items
+                .iter()
+                .map(|t| eval_ast(t, vars, funcs, visited))
+                .collect::<Result<Vec<_>, _>>()?;
 
-            match flat.len() {
-                0 => Expr::Number(1.0),
-                1 => flat.pop().unwrap(),
-                _ => Expr::Mul(flat),
-            }
+            Ok(Expr::List(evaluated))
         }
 
+        Expr::Call(name, args) => {
+            let evaluated = args
+                .iter()
+                .map(|a| eval_ast(a, vars, funcs, visited))
+                .collect::<Result<Vec<_>, _>>()?;
 
-        Expr::Neg(e) => match normalize(*e) {
-            Expr::Number(n) => Expr::Number(-n),
-            other => Expr::Neg(Box::new(other)),
-        },
+            match funcs.get(name) {
+                Some(function) => call_user_function(name, function, evaluated, vars, funcs, visited),
+                None => call_builtin(name, evaluated),
+            }
+        }
+    }
+}
+
+// diff(f, x): f is expanded with x kept symbolic, differentiated, and the result evaluated
+// normally so a defined x gives the slope at that point
+fn eval_diff(args: &[Expr], vars: &HashMap<String, Expr>, funcs: &HashMap<String, UserFunction>, visited: &mut HashSet<String>) -> Result<Expr, CalcError> {
+    let (body, var) = match args {
+        [body, Expr::Var(var)] => (body, var),
+        [_, _] => return Err(CalcError::InvalidExpression("Second argument of diff has to be a variable".into())),
+        _ => return Err(CalcError::ArityMismatch("diff".into(), 2, args.len())),
+    };
+
+    let mut scope_visited = visited.clone();
+    scope_visited.insert(var.clone());
+    let expanded = eval_ast(body, vars, funcs, &mut scope_visited)?;
+    let derivative = differentiate(&expanded, var)?;
+    eval_ast(&derivative, vars, funcs, visited)
+}
 
-        Expr::Div(a, b) => {
-            let left = normalize(*a);
-            let right = normalize(*b);
-
-            match (&left, &right) {
-                (Expr::Div(x, y), Expr::Number(bn)) => {
-                    match **y {
-                        Expr::Number(an) => {
-                            Expr::Div(
-                                x.clone(),
-                                Box::new(Expr::Number(an * bn)),
-                            )
-                        }
-                        _ => Expr::Div(Box::new(left), Box::new(right)),
-                    }
-                }
-
-                (Expr::Number(x), Expr::Number(y)) => Expr::Number(x / y),
-
-                _ => Expr::Div(Box::new(left), Box::new(right)),
+// solve(lhs == rhs, x) or solve(lhs == rhs, x, lo, hi), where lo and hi bound the search of the
+// numeric fallback. Both sides are expanded with x kept symbolic, like in diff
+fn eval_solve(args: &[Expr], vars: &HashMap<String, Expr>, funcs: &HashMap<String, UserFunction>, visited: &mut HashSet<String>) -> Result<Expr, CalcError> {
+    let (equation, var, bounds) = match args {
+        [equation, Expr::Var(var)] => (equation, var, None),
+        [equation, Expr::Var(var), lo, hi] => (equation, var, Some((lo, hi))),
+        [_, _] | [_, _, _, _] => return Err(CalcError::InvalidExpression("Second argument of solve has to be a variable".into())),
+        _ => return Err(CalcError::ArityMismatch("solve".into(), 2, args.len())),
+    };
+
+    let bracket = match bounds {
+        Some((lo, hi)) => match (eval_ast(lo, vars, funcs, visited)?, eval_ast(hi, vars, funcs, visited)?) {
+            (Expr::Number(lo), Expr::Number(hi)) if lo > hi => {
+                return Err(CalcError::InvalidExpression(format!("The bounds of solve are the wrong way around, {} is above {}", lo, hi)));
             }
+            (Expr::Number(lo), Expr::Number(hi)) => Some((lo.to_f64(), hi.to_f64())),
+            _ => return Err(CalcError::InvalidExpression("Bounds of solve have to be numbers".into())),
         },
+        None => None,
+    };
+
+    let mut scope_visited = visited.clone();
+    scope_visited.insert(var.clone());
+    let difference = match equation {
+        Expr::Equation(lhs, rhs) => Expr::Sub(
+            Box::new(eval_ast(lhs, vars, funcs, &mut scope_visited)?),
+            Box::new(eval_ast(rhs, vars, funcs, &mut scope_visited)?),
+        ),
+        other => eval_ast(other, vars, funcs, &mut scope_visited)?,
+    };
+    if !depends_on(&difference, var) {
+        return Err(CalcError::InvalidExpression(format!("{} does not occur in the equation", var)));
+    }
+
+    let mut solutions = solve(&difference, var, bracket)?
+        .iter()
+        .map(|s| eval_ast(s, vars, funcs, visited))
+        .collect::<Result<Vec<_>, _>>()?;
+    // roots like sqrt(4) are only numbers once evaluated, so the bounds are applied after that
+    if let Some((lo, hi)) = bracket {
+        solutions.retain(|s| match s {
+            Expr::Number(n) => n.to_f64() >= lo && n.to_f64() <= hi,
+            _ => true,
+        });
+    }
+    Ok(Expr::List(solutions))
+}
+
+// binds the parameters over the global variables and evaluates the body in that scope
+fn call_user_function(name: &str, function: &UserFunction, args: Vec<Expr>, vars: &HashMap<String, Expr>, funcs: &HashMap<String, UserFunction>, visited: &mut HashSet<String>) -> Result<Expr, CalcError> {
+    if args.len() != function.params.len() {
+        return Err(CalcError::ArityMismatch(name.to_string(), function.params.len(), args.len()));
+    }
 
-        other => other,
+    // same trick as for variables, a function already being expanded stays symbolic
+    let marker = format!("{}()", name);
+    if visited.contains(&marker) {
+        return Ok(Expr::Call(name.to_string(), args));
     }
-}
\ No newline at end of file
+
+    let mut scope = vars.clone();
+    let mut scope_visited = visited.clone();
+    for (param, arg) in function.params.iter().zip(args) {
+        scope_visited.remove(param);
+        scope.insert(param.clone(), arg);
+    }
+    scope_visited.insert(marker);
+
+    eval_ast(&function.body, &scope, funcs, &mut scope_visited)
+}
@@ -0,0 +1,177 @@
+use crate::engine::Expr;
+use crate::engine::Number;
+use crate::engine::engine::Value;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Signed, Zero};
+use std::fmt;
+
+// binding strength of what got printed, a child binding weaker than its slot needs parentheses
+pub const EQUATION: u8 = 1;
+pub const SUM: u8 = 2;
+pub const PRODUCT: u8 = 3;
+pub const QUOTIENT_RHS: u8 = 4;
+pub const POWER: u8 = 5;
+pub const ATOM: u8 = 6;
+
+// digits after the point for fractions printed as decimals when no precision is set
+const DEFAULT_DECIMALS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct NumberFormat {
+    pub fractions: bool, // 1/3 instead of 0.333333333333
+    pub precision: Option<usize>, // digits after the point for decimals, None for as many as needed
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            fractions: true,
+            precision: None,
+        }
+    }
+}
+
+impl NumberFormat {
+    pub fn number(&self, n: &Number) -> String {
+        match n {
+            Number::Integer(i) => i.to_string(),
+            Number::Rational(r) if self.fractions => format!("{}/{}", r.numer(), r.denom()),
+            Number::Rational(r) => decimal(r, self.precision.unwrap_or(DEFAULT_DECIMALS)),
+            Number::Float(f) => match self.precision {
+                Some(digits) if f.is_finite() => trim_zeros(format!("{:.*}", digits, f)),
+                _ => n.to_string(),
+            },
+        }
+    }
+}
+
+fn trim_zeros(s: String) -> String {
+    if !s.contains('.') {
+        return s;
+    }
+    let trimmed = s.trim_end_matches('0').trim_end_matches('.');
+    if trimmed == "-0" { "0".to_string() } else { trimmed.to_string() }
+}
+
+// exact long division so huge fractions print without going through f64
+fn decimal(r: &BigRational, digits: usize) -> String {
+    let scale = num_traits::pow(BigInt::from(10), digits);
+    // rounded half up: floor((|n| * 10^digits * 2 + d) / (2 * d))
+    let numer: BigInt = r.numer().abs() * &scale * 2 + r.denom();
+    let scaled = numer.div_floor(&(r.denom() * 2));
+    let (int_part, frac_part) = scaled.div_rem(&scale);
+    let sign = if r.is_negative() && !scaled.is_zero() { "-" } else { "" };
+    if digits == 0 {
+        return format!("{}{}", sign, int_part);
+    }
+    trim_zeros(format!("{}{}.{:0>width$}", sign, int_part, frac_part.to_string(), width = digits))
+}
+
+fn wrap(expr: &Expr, format: &NumberFormat, min: u8) -> String {
+    let (text, strength) = render(expr, format);
+    if strength < min { format!("({})", text) } else { text }
+}
+
+fn render(expr: &Expr, format: &NumberFormat) -> (String, u8) {
+    match expr {
+        Expr::Number(n) => {
+            let text = format.number(n);
+            let strength = if text.starts_with('-') {
+                SUM
+            } else if text.contains('/') {
+                PRODUCT
+            } else {
+                ATOM
+            };
+            (text, strength)
+        }
+        Expr::Var(name) => (name.clone(), ATOM),
+        Expr::Call(name, args) => {
+            let args: Vec<String> = args.iter().map(|a| wrap(a, format, EQUATION)).collect();
+            (format!("{}({})", name, args.join(", ")), ATOM)
+        }
+        Expr::List(items) => {
+            let items: Vec<String> = items.iter().map(|a| wrap(a, format, EQUATION)).collect();
+            (format!("[{}]", items.join(", ")), ATOM)
+        }
+        // ^ gets parentheses on both sides when nested, so it reads the same whichever way it associates
+        Expr::Pow(base, exp) => (format!("{}^{}", wrap(base, format, ATOM), wrap(exp, format, ATOM)), POWER),
+        Expr::Neg(inner) => (format!("-{}", wrap(inner, format, PRODUCT)), SUM),
+        Expr::Mul(factors) => {
+            let factors: Vec<String> = factors.iter().map(|f| wrap(f, format, PRODUCT)).collect();
+            (factors.join("*"), PRODUCT)
+        }
+        Expr::Div(a, b) => (format!("{}/{}", wrap(a, format, PRODUCT), wrap(b, format, QUOTIENT_RHS)), PRODUCT),
+        Expr::Sub(a, b) => (format!("{} - {}", wrap(a, format, SUM), wrap(b, format, PRODUCT)), SUM),
+        Expr::Add(terms) => {
+            let mut text = String::new();
+            for (i, term) in terms.iter().enumerate() {
+                if i == 0 {
+                    text.push_str(&wrap(term, format, SUM));
+                    continue;
+                }
+                // x + -y reads as x - y
+                match term {
+                    Expr::Neg(inner) => text.push_str(&format!(" - {}", wrap(inner, format, PRODUCT))),
+                    Expr::Number(n) if *n < Number::zero() => {
+                        text.push_str(&format!(" - {}", wrap(&Expr::Number(-n.clone()), format, PRODUCT)))
+                    }
+                    _ => text.push_str(&format!(" + {}", wrap(term, format, SUM))),
+                }
+            }
+            (text, SUM)
+        }
+        Expr::Equation(a, b) => (format!("{} == {}", wrap(a, format, SUM), wrap(b, format, SUM)), EQUATION),
+        Expr::Inequality(a, relation, b) => (format!("{} {} {}", wrap(a, format, SUM), relation, wrap(b, format, SUM)), EQUATION),
+    }
+}
+
+// Display with an explicit number format, from Expr::display_with and Value::display_with
+pub struct Formatted<'a, T> {
+    value: &'a T,
+    format: NumberFormat,
+}
+
+impl Expr {
+    pub fn display_with(&self, format: NumberFormat) -> Formatted<'_, Expr> {
+        Formatted { value: self, format }
+    }
+}
+
+impl Value {
+    pub fn display_with(&self, format: NumberFormat) -> Formatted<'_, Value> {
+        Formatted { value: self, format }
+    }
+}
+
+impl fmt::Display for Formatted<'_, Expr> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", render(self.value, &self.format).0)
+    }
+}
+
+impl fmt::Display for Formatted<'_, Value> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            Value::Number(n) => write!(f, "{}", self.format.number(n)),
+            Value::Expression(expr) => write!(f, "{}", expr.display_with(self.format)),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display_with(NumberFormat::default()))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display_with(NumberFormat::default()))
+    }
+}
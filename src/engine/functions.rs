@@ -0,0 +1,183 @@
+use crate::engine::CalcError;
+use crate::engine::Expr;
+use crate::engine::Number;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Signed, ToPrimitive};
+
+// function defined in the session with "f(x, y) = ...", the body is kept unevaluated
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserFunction {
+    pub params: Vec<String>,
+    pub body: Expr,
+}
+
+// Float kernels always go through f64 and give a Float back, Exact kernels keep rationals exact
+pub enum Kernel {
+    Float(fn(&[f64]) -> f64),
+    Exact(fn(&[Number]) -> Result<Number, CalcError>),
+}
+
+pub struct Function {
+    pub name: &'static str,
+    pub arity: usize,
+    pub kernel: Kernel,
+}
+
+const fn float(name: &'static str, arity: usize, apply: fn(&[f64]) -> f64) -> Function {
+    Function { name, arity, kernel: Kernel::Float(apply) }
+}
+
+const fn exact(name: &'static str, arity: usize, apply: fn(&[Number]) -> Result<Number, CalcError>) -> Function {
+    Function { name, arity, kernel: Kernel::Exact(apply) }
+}
+
+// registry of the built-in functions, looked up by name when a Call gets evaluated
+pub const FUNCTIONS: &[Function] = &[
+    float("sin", 1, |a| a[0].sin()),
+    float("cos", 1, |a| a[0].cos()),
+    float("tan", 1, |a| a[0].tan()),
+    float("asin", 1, |a| a[0].asin()),
+    float("acos", 1, |a| a[0].acos()),
+    float("atan", 1, |a| a[0].atan()),
+    float("atan2", 2, |a| a[0].atan2(a[1])),
+    float("sinh", 1, |a| a[0].sinh()),
+    float("cosh", 1, |a| a[0].cosh()),
+    float("tanh", 1, |a| a[0].tanh()),
+    exact("sqrt", 1, |a| match a[0].exact_sqrt() {
+        Some(root) => Ok(root),
+        None => float_result("sqrt", a[0].to_f64().sqrt(), a),
+    }),
+    float("cbrt", 1, |a| a[0].cbrt()),
+    float("exp", 1, |a| a[0].exp()),
+    float("ln", 1, |a| a[0].ln()),
+    float("log", 1, |a| a[0].log10()),
+    float("log2", 1, |a| a[0].log2()),
+    exact("abs", 1, |a| Ok(a[0].abs())),
+    exact("floor", 1, |a| Ok(a[0].floor())),
+    exact("ceil", 1, |a| Ok(a[0].ceil())),
+    exact("round", 1, |a| Ok(a[0].round())),
+    exact("min", 2, |a| Ok(if a[1] < a[0] { a[1].clone() } else { a[0].clone() })),
+    exact("max", 2, |a| Ok(if a[1] > a[0] { a[1].clone() } else { a[0].clone() })),
+    exact("float", 1, |a| Ok(a[0].to_float())),
+    exact("fact", 1, |a| factorial(&integer_arg("fact", &a[0])?)),
+    exact("binom", 2, |a| binomial(&integer_arg("binom", &a[0])?, &integer_arg("binom", &a[1])?)),
+    exact("perm", 2, |a| permutations(&integer_arg("perm", &a[0])?, &integer_arg("perm", &a[1])?)),
+    exact("gcd", 2, |a| Ok(Number::from(integer_arg("gcd", &a[0])?.gcd(&integer_arg("gcd", &a[1])?)))),
+    exact("lcm", 2, |a| Ok(Number::from(integer_arg("lcm", &a[0])?.lcm(&integer_arg("lcm", &a[1])?)))),
+];
+
+// factorial and friends beyond this size would take too long to compute exactly
+const MAX_COMBINATORIC_STEPS: u64 = 100_000;
+
+// calls the evaluator handles itself because their arguments are not plain values, table is run
+// by the engine as a statement of its own
+pub const SPECIAL_FORMS: &[&str] = &["diff", "solve", "table"];
+
+pub fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+pub fn is_reserved(name: &str) -> bool {
+    lookup(name).is_some() || SPECIAL_FORMS.contains(&name)
+}
+
+// ans and _ for the last result, $1, $2, ... for earlier ones
+pub fn is_history_name(name: &str) -> bool {
+    match name.strip_prefix('$') {
+        Some(index) => !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()),
+        None => name == "ans" || name == "_",
+    }
+}
+
+// a name the user can give a variable or function: starts with a letter, no built-in
+pub fn is_valid_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !is_reserved(name)
+        && !is_history_name(name)
+}
+
+fn float_result(name: &str, result: f64, args: &[Number]) -> Result<Number, CalcError> {
+    if result.is_nan() && args.iter().all(|n| !n.to_f64().is_nan()) {
+        let args: Vec<String> = args.iter().map(|n| n.to_string()).collect();
+        Err(CalcError::DomainError(format!("{}({})", name, args.join(", "))))
+    } else {
+        Ok(Number::Float(result))
+    }
+}
+
+fn integer_arg(name: &str, arg: &Number) -> Result<BigInt, CalcError> {
+    arg.to_integer().ok_or(CalcError::DomainError(format!("{} expects whole numbers, got {}", name, arg)))
+}
+
+// product of the `count` consecutive integers ending at `top`, i.e. top * (top - 1) * ...
+fn falling_product(top: &BigInt, count: &BigInt) -> Result<BigInt, CalcError> {
+    let steps = count.to_u64().filter(|s| *s <= MAX_COMBINATORIC_STEPS)
+        .ok_or(CalcError::DomainError(format!("{} factors are too many to multiply exactly", count)))?;
+    let mut result = BigInt::one();
+    let mut factor = top.clone();
+    for _ in 0..steps {
+        result *= &factor;
+        factor -= 1;
+    }
+    Ok(result)
+}
+
+fn factorial(n: &BigInt) -> Result<Number, CalcError> {
+    if n.is_negative() {
+        return Err(CalcError::DomainError(format!("fact({}) is not defined for negative numbers", n)));
+    }
+    Ok(Number::from(falling_product(n, n)?))
+}
+
+fn permutations(n: &BigInt, k: &BigInt) -> Result<Number, CalcError> {
+    if n.is_negative() || k.is_negative() {
+        return Err(CalcError::DomainError(format!("perm({}, {}) is not defined for negative numbers", n, k)));
+    }
+    if k > n {
+        return Ok(Number::zero());
+    }
+    Ok(Number::from(falling_product(n, k)?))
+}
+
+fn binomial(n: &BigInt, k: &BigInt) -> Result<Number, CalcError> {
+    if n.is_negative() || k.is_negative() {
+        return Err(CalcError::DomainError(format!("binom({}, {}) is not defined for negative numbers", n, k)));
+    }
+    if k > n {
+        return Ok(Number::zero());
+    }
+    let k = k.min(&(n - k)).clone();
+    Ok(Number::from(falling_product(n, &k)? / falling_product(&k, &k)?))
+}
+
+// numeric arguments get evaluated, anything symbolic is passed through as a Call
+pub fn call_builtin(name: &str, args: Vec<Expr>) -> Result<Expr, CalcError> {
+    let function = lookup(name).ok_or(CalcError::UnknownFunction(name.to_string()))?;
+    if args.len() != function.arity {
+        return Err(CalcError::ArityMismatch(name.to_string(), function.arity, args.len()));
+    }
+
+    let numbers: Option<Vec<Number>> = args
+        .iter()
+        .map(|a| match a {
+            Expr::Number(n) => Some(n.clone()),
+            _ => None,
+        })
+        .collect();
+
+    match numbers {
+        Some(numbers) => {
+            let result = match &function.kernel {
+                Kernel::Float(apply) => {
+                    let floats: Vec<f64> = numbers.iter().map(Number::to_f64).collect();
+                    float_result(name, apply(&floats), &numbers)?
+                }
+                Kernel::Exact(apply) => apply(&numbers)?,
+            };
+            Ok(Expr::Number(result))
+        }
+        None => Ok(Expr::Call(name.to_string(), args)),
+    }
+}
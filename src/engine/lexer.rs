@@ -1,49 +1,108 @@
 use crate::engine::CalcError;
+use crate::engine::Number;
+use crate::engine::diagnostic::{Diagnostic, Span};
+use std::fmt;
 
-/*pub enum Tokens{
-    Number(f64),
-    Op(String),
-    LParen,
-    RParen,
-    Var(String),
-    Func(String) <--- for basic functions like sin, cos. Will think about the implementation later.
-*/
 #[derive(Debug, Clone, PartialEq)]
-pub enum Token{ //will think about changing the Tokens for operators to one token with string like above
-    Number(f64),
+#[non_exhaustive]
+pub enum TokenKind{ //will think about changing the Tokens for operators to one token with string like above
+    Number(Number),
     Plus,
     PlusEqual,
     Minus,
-    UnaryMinus,
     MinusEqual,
     Star,
     StarEqual,
     Slash,
     SlashEqual,
     Power,
+    Factorial, // postfix "!"
     LParen,
     RParen,
     Var(String),
+    Func(String), // identifier directly followed by "(", resolved against the function registry in the evaluator
+    Comma,
     Equal,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
     Assign,
+    Bind, // :=, assignment that stays live
+    Text(String), // "quoted", only used for the file of save and load
     EndOfFile,
 }
 
-fn push_op(chars: &mut std::iter::Peekable<std::str::Chars>, tokens: &mut Vec<Token>, normal: Token, compound: Token, ) {
-    chars.next();
-    if chars.peek() == Some(&'=') {
-        chars.next();
-        tokens.push(compound);
-    } else {
-        tokens.push(normal);
+// how a token reads in error messages
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            TokenKind::Number(n) => return write!(f, "number {}", n),
+            TokenKind::Var(name) => return write!(f, "name {}", name),
+            TokenKind::Func(name) => return write!(f, "function {}", name),
+            TokenKind::Text(text) => return write!(f, "text \"{}\"", text),
+            TokenKind::Plus => "+",
+            TokenKind::PlusEqual => "+=",
+            TokenKind::Minus => "-",
+            TokenKind::MinusEqual => "-=",
+            TokenKind::Star => "*",
+            TokenKind::StarEqual => "*=",
+            TokenKind::Slash => "/",
+            TokenKind::SlashEqual => "/=",
+            TokenKind::Power => "^",
+            TokenKind::Factorial => "!",
+            TokenKind::LParen => "(",
+            TokenKind::RParen => ")",
+            TokenKind::Comma => ",",
+            TokenKind::Equal => "==",
+            TokenKind::Less => "<",
+            TokenKind::LessEqual => "<=",
+            TokenKind::Greater => ">",
+            TokenKind::GreaterEqual => ">=",
+            TokenKind::Assign => "=",
+            TokenKind::Bind => ":=",
+            TokenKind::EndOfFile => ";",
+        };
+        write!(f, "'{}'", symbol)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, span: Span) -> Self {
+        Self { kind, span }
     }
 }
 
-pub fn tokenize(input: &str) -> Result<Vec<Token>, CalcError> {
+type Chars<'a> = std::iter::Peekable<std::str::CharIndices<'a>>;
+
+// byte offset of the next unread char
+fn offset(chars: &mut Chars, input: &str) -> usize {
+    chars.peek().map_or(input.len(), |(i, _)| *i)
+}
+
+fn push_op(chars: &mut Chars, input: &str, tokens: &mut Vec<Token>, normal: TokenKind, compound: TokenKind) {
+    let (start, _) = chars.next().unwrap();
+    let kind = if chars.next_if(|(_, c)| *c == '=').is_some() { compound } else { normal };
+    tokens.push(Token::new(kind, Span::new(start, offset(chars, input))));
+}
+
+fn push_single(chars: &mut Chars, input: &str, tokens: &mut Vec<Token>, kind: TokenKind) {
+    let (start, _) = chars.next().unwrap();
+    tokens.push(Token::new(kind, Span::new(start, offset(chars, input))));
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, Diagnostic> {
     let mut tokens: Vec<Token> = Vec::new();
-    let mut chars = input.chars().peekable();
+    let mut chars: Chars = input.char_indices().peekable();
 
-    while let Some(&ch) = chars.peek() {
+    while let Some(&(start, ch)) = chars.peek() {
         match ch{
             '0'..='9'|'.' => {
                 let mut acc = String::new();
@@ -51,66 +110,85 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, CalcError> {
                     acc.push_str("0.");
                     chars.next();
                 }
-                while let Some(&c) = chars.peek() {
-                    if c.is_ascii_digit() || c == '.' {
-                        acc.push(c);
-                        chars.next();
-                    } else {
-                        break;
-                    }
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                    acc.push(c);
                 }
 
-                let value = acc.parse::<f64>()
-                    .map_err(|_|{CalcError::InvalidExpression(acc)});
-                tokens.push(Token::Number(value?));
+                let span = Span::new(start, offset(&mut chars, input));
+                let value = acc.parse::<Number>().map_err(|e| e.at(span))?;
+                tokens.push(Token::new(TokenKind::Number(value), span));
             },
-            '+' => push_op(&mut chars, &mut tokens, Token::Plus, Token::PlusEqual),
-            '-' => push_op(&mut chars, &mut tokens, Token::Minus, Token::MinusEqual),
-            '*' => push_op(&mut chars, &mut tokens, Token::Star, Token::StarEqual),
-            '/' => push_op(&mut chars, &mut tokens, Token::Slash, Token::SlashEqual),
-            '(' => {
+            '+' => push_op(&mut chars, input, &mut tokens, TokenKind::Plus, TokenKind::PlusEqual),
+            '-' => push_op(&mut chars, input, &mut tokens, TokenKind::Minus, TokenKind::MinusEqual),
+            '*' => push_op(&mut chars, input, &mut tokens, TokenKind::Star, TokenKind::StarEqual),
+            '/' => push_op(&mut chars, input, &mut tokens, TokenKind::Slash, TokenKind::SlashEqual),
+            '(' => push_single(&mut chars, input, &mut tokens, TokenKind::LParen),
+            ')' => push_single(&mut chars, input, &mut tokens, TokenKind::RParen),
+            ',' => push_single(&mut chars, input, &mut tokens, TokenKind::Comma),
+            '=' => push_op(&mut chars, input, &mut tokens, TokenKind::Assign, TokenKind::Equal),
+            '<' => push_op(&mut chars, input, &mut tokens, TokenKind::Less, TokenKind::LessEqual),
+            '>' => push_op(&mut chars, input, &mut tokens, TokenKind::Greater, TokenKind::GreaterEqual),
+            ':' if input[start + 1..].starts_with('=') => {
                 chars.next();
-                tokens.push(Token::LParen);
-            },
-            ')' => {
                 chars.next();
-                tokens.push(Token::RParen);
+                tokens.push(Token::new(TokenKind::Bind, Span::new(start, start + 2)));
             },
-            '=' => push_op(&mut chars, &mut tokens, Token::Assign, Token::Equal),
 
-            '^' =>  {
-                chars.next();
-                tokens.push(Token::Power);
-            },
+            '!' => push_single(&mut chars, input, &mut tokens, TokenKind::Factorial),
+            '^' => push_single(&mut chars, input, &mut tokens, TokenKind::Power),
             c if c.is_ascii_whitespace() => {
                 chars.next();
             },
-            c if c.is_ascii_alphanumeric() => {
+            // $3 is the third result in the history
+            '$' if input[start + 1..].starts_with(|c: char| c.is_ascii_digit()) => {
                 let mut acc = String::new();
-                while let Some(&ch) = chars.peek() {
-                    if ch.is_ascii_alphanumeric() || c == '_' {
-                        acc.push(ch);
-                        chars.next();
-                    }
-                    else{
-                        break;
-                    }
+                while let Some((_, ch)) = chars.next_if(|(i, ch)| *i == start || ch.is_ascii_digit()) {
+                    acc.push(ch);
                 }
-                tokens.push(Token::Var(acc));
+                let span = Span::new(start, offset(&mut chars, input));
+                tokens.push(Token::new(TokenKind::Var(acc), span));
             },
-            ';' => {
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut acc = String::new();
+                while let Some((_, ch)) = chars.next_if(|(_, ch)| ch.is_ascii_alphanumeric() || *ch == '_') {
+                    acc.push(ch);
+                }
+                let span = Span::new(start, offset(&mut chars, input));
+                // "sin(x)" and "sin (x)" are calls, a lone "sin" stays a variable
+                let mut lookahead = chars.clone();
+                while lookahead.next_if(|(_, c)| c.is_ascii_whitespace()).is_some() {}
+                if lookahead.peek().is_some_and(|(_, c)| *c == '(') {
+                    tokens.push(Token::new(TokenKind::Func(acc), span));
+                } else {
+                    tokens.push(Token::new(TokenKind::Var(acc), span));
+                }
+            },
+            '"' => {
                 chars.next();
-                tokens.push(Token::EndOfFile);
-            }
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => text.push(c),
+                        None => {
+                            let span = Span::new(start, start + 1);
+                            return Err(CalcError::InvalidExpression("Missing closing '\"'".into()).at(span));
+                        }
+                    }
+                }
+                tokens.push(Token::new(TokenKind::Text(text), Span::new(start, offset(&mut chars, input))));
+            },
+            ';' => push_single(&mut chars, input, &mut tokens, TokenKind::EndOfFile),
             _ => {
-                return Err(CalcError::InvalidExpression(ch.to_string()));
+                let span = Span::new(start, start + ch.len_utf8());
+                return Err(CalcError::InvalidExpression(format!("Unexpected character '{}'", ch)).at(span));
             }
         }
     }
     if tokens.is_empty() {
-        Err(CalcError::EmptyExpression)
+        Err(CalcError::EmptyExpression.into())
     }
     else {
         Ok(tokens)
     }
-}
\ No newline at end of file
+}
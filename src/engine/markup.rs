@@ -0,0 +1,317 @@
+use crate::engine::{Expr, Relation};
+use crate::engine::Number;
+use crate::engine::NumberFormat;
+use crate::engine::engine::Value;
+use crate::engine::format::{ATOM, EQUATION, POWER, PRODUCT, SUM};
+
+// LaTeX and presentation MathML for pasting results into documents. Both walk the tree the
+// same way Display does and use the same binding strengths, only fractions, powers and
+// roots are laid out in two dimensions so they never need parentheses around them.
+
+const GREEK: &[(&str, &str, char)] = &[
+    ("alpha", "\\alpha", 'α'),
+    ("beta", "\\beta", 'β'),
+    ("gamma", "\\gamma", 'γ'),
+    ("delta", "\\delta", 'δ'),
+    ("epsilon", "\\epsilon", 'ε'),
+    ("theta", "\\theta", 'θ'),
+    ("lambda", "\\lambda", 'λ'),
+    ("mu", "\\mu", 'μ'),
+    ("pi", "\\pi", 'π'),
+    ("rho", "\\rho", 'ρ'),
+    ("sigma", "\\sigma", 'σ'),
+    ("tau", "\\tau", 'τ'),
+    ("phi", "\\phi", 'φ'),
+    ("omega", "\\omega", 'ω'),
+];
+
+// built-ins that are typeset upright as an operator name, (name, LaTeX command, MathML text)
+const OPERATOR_NAMES: &[(&str, &str, &str)] = &[
+    ("sin", "\\sin", "sin"),
+    ("cos", "\\cos", "cos"),
+    ("tan", "\\tan", "tan"),
+    ("asin", "\\arcsin", "arcsin"),
+    ("acos", "\\arccos", "arccos"),
+    ("atan", "\\arctan", "arctan"),
+    ("sinh", "\\sinh", "sinh"),
+    ("cosh", "\\cosh", "cosh"),
+    ("tanh", "\\tanh", "tanh"),
+    ("exp", "\\exp", "exp"),
+    ("ln", "\\ln", "ln"),
+    ("log", "\\log", "log"),
+    ("log2", "\\log_{2}", "log₂"),
+    ("min", "\\min", "min"),
+    ("max", "\\max", "max"),
+    ("gcd", "\\gcd", "gcd"),
+];
+
+fn is_negative(n: &Number) -> bool {
+    *n < Number::zero()
+}
+
+pub fn value_to_latex(value: &Value, format: &NumberFormat) -> String {
+    match value {
+        Value::Number(n) => latex(&Expr::Number(n.clone()), format).0,
+        Value::Expression(expr) => latex(expr, format).0,
+        Value::Bool(b) => format!("\\text{{{}}}", b),
+        Value::Text(text) => format!("\\text{{{}}}", escape_latex(text)),
+    }
+}
+
+pub fn value_to_mathml(value: &Value, format: &NumberFormat) -> String {
+    let body = match value {
+        Value::Number(n) => mathml(&Expr::Number(n.clone()), format).0,
+        Value::Expression(expr) => mathml(expr, format).0,
+        Value::Bool(b) => format!("<mtext>{}</mtext>", b),
+        Value::Text(text) => format!("<mtext>{}</mtext>", escape_xml(text)),
+    };
+    format!("<math xmlns=\"http://www.w3.org/1998/Math/MathML\">{}</math>", body)
+}
+
+fn escape_latex(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\\' => "\\textbackslash{}".to_string(),
+            '~' => "\\textasciitilde{}".to_string(),
+            '^' => "\\textasciicircum{}".to_string(),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => format!("\\{}", c),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// ---- LaTeX ----
+
+fn latex_name(name: &str) -> String {
+    let (base, subscript) = match name.split_once('_') {
+        Some((base, subscript)) if !base.is_empty() && !subscript.is_empty() => (base, Some(subscript)),
+        _ => (name, None),
+    };
+    let base = match GREEK.iter().find(|(n, _, _)| *n == base) {
+        Some((_, command, _)) => command.to_string(),
+        None if base.chars().count() == 1 => base.to_string(),
+        None => format!("\\mathrm{{{}}}", base.replace('_', "\\_")),
+    };
+    match subscript {
+        Some(subscript) => format!("{}_{{{}}}", base, latex_name(subscript)),
+        None => base,
+    }
+}
+
+fn latex_wrap(expr: &Expr, format: &NumberFormat, min: u8) -> String {
+    let (text, strength) = latex(expr, format);
+    if strength < min { format!("\\left({}\\right)", text) } else { text }
+}
+
+fn latex_number(n: &Number, format: &NumberFormat) -> (String, u8) {
+    let sign = if is_negative(n) { "-" } else { "" };
+    match n {
+        Number::Rational(r) if format.fractions => {
+            let strength = if is_negative(n) { SUM } else { PRODUCT };
+            (format!("{}\\frac{{{}}}{{{}}}", sign, r.numer().magnitude(), r.denom()), strength)
+        }
+        _ => {
+            let text = format.number(n);
+            (text, if is_negative(n) { SUM } else { ATOM })
+        }
+    }
+}
+
+fn latex(expr: &Expr, format: &NumberFormat) -> (String, u8) {
+    match expr {
+        Expr::Number(n) => latex_number(n, format),
+        Expr::Var(name) => (latex_name(name), ATOM),
+        Expr::Call(name, args) => {
+            let rendered: Vec<String> = args.iter().map(|a| latex(a, format).0).collect();
+            let text = match (name.as_str(), rendered.as_slice()) {
+                ("sqrt", [a]) => format!("\\sqrt{{{}}}", a),
+                ("cbrt", [a]) => format!("\\sqrt[3]{{{}}}", a),
+                ("abs", [a]) => format!("\\left|{}\\right|", a),
+                ("fact", [_]) => format!("{}!", latex_wrap(&args[0], format, ATOM)),
+                _ => {
+                    let operator = match OPERATOR_NAMES.iter().find(|(n, _, _)| n == name) {
+                        Some((_, command, _)) => command.to_string(),
+                        None => format!("\\operatorname{{{}}}", latex_name(name)),
+                    };
+                    format!("{}\\left({}\\right)", operator, rendered.join(", "))
+                }
+            };
+            (text, ATOM)
+        }
+        Expr::List(items) => {
+            let items: Vec<String> = items.iter().map(|a| latex(a, format).0).collect();
+            (format!("\\left[{}\\right]", items.join(", ")), ATOM)
+        }
+        Expr::Pow(base, exp) => (format!("{}^{{{}}}", latex_wrap(base, format, ATOM), latex(exp, format).0), POWER),
+        Expr::Neg(inner) => (format!("-{}", latex_wrap(inner, format, PRODUCT)), SUM),
+        Expr::Mul(factors) => {
+            let mut text = String::new();
+            for (i, factor) in factors.iter().enumerate() {
+                let factor = latex_wrap(factor, format, PRODUCT);
+                // 2 \cdot 3 keeps the digits apart, 2 x is plain juxtaposition
+                if i > 0 {
+                    let digit = factor.starts_with(|c: char| c.is_ascii_digit()) || factor.starts_with("\\frac");
+                    text.push_str(if digit { " \\cdot " } else { " " });
+                }
+                text.push_str(&factor);
+            }
+            (text, PRODUCT)
+        }
+        Expr::Div(a, b) => (format!("\\frac{{{}}}{{{}}}", latex(a, format).0, latex(b, format).0), PRODUCT),
+        Expr::Sub(a, b) => (format!("{} - {}", latex_wrap(a, format, SUM), latex_wrap(b, format, PRODUCT)), SUM),
+        Expr::Add(terms) => {
+            let mut text = String::new();
+            for (i, term) in terms.iter().enumerate() {
+                if i == 0 {
+                    text.push_str(&latex_wrap(term, format, SUM));
+                    continue;
+                }
+                match term {
+                    Expr::Neg(inner) => text.push_str(&format!(" - {}", latex_wrap(inner, format, PRODUCT))),
+                    Expr::Number(n) if is_negative(n) => {
+                        text.push_str(&format!(" - {}", latex_wrap(&Expr::Number(-n.clone()), format, PRODUCT)))
+                    }
+                    _ => text.push_str(&format!(" + {}", latex_wrap(term, format, SUM))),
+                }
+            }
+            (text, SUM)
+        }
+        Expr::Equation(a, b) => (format!("{} = {}", latex_wrap(a, format, SUM), latex_wrap(b, format, SUM)), EQUATION),
+        Expr::Inequality(a, relation, b) => {
+            let symbol = match relation {
+                Relation::Less => "<",
+                Relation::LessEqual => "\\le",
+                Relation::Greater => ">",
+                Relation::GreaterEqual => "\\ge",
+            };
+            (format!("{} {} {}", latex_wrap(a, format, SUM), symbol, latex_wrap(b, format, SUM)), EQUATION)
+        }
+    }
+}
+
+// ---- MathML ----
+
+fn mathml_name(name: &str) -> String {
+    let (base, subscript) = match name.split_once('_') {
+        Some((base, subscript)) if !base.is_empty() && !subscript.is_empty() => (base, Some(subscript)),
+        _ => (name, None),
+    };
+    let base = match GREEK.iter().find(|(n, _, _)| *n == base) {
+        Some((_, _, letter)) => format!("<mi>{}</mi>", letter),
+        None => format!("<mi>{}</mi>", base),
+    };
+    match subscript {
+        Some(subscript) if subscript.chars().all(|c| c.is_ascii_digit()) => {
+            format!("<msub>{}<mn>{}</mn></msub>", base, subscript)
+        }
+        Some(subscript) => format!("<msub>{}{}</msub>", base, mathml_name(subscript)),
+        None => base,
+    }
+}
+
+fn mathml_wrap(expr: &Expr, format: &NumberFormat, min: u8) -> String {
+    let (text, strength) = mathml(expr, format);
+    if strength < min { parenthesized(&text) } else { text }
+}
+
+fn parenthesized(text: &str) -> String {
+    format!("<mrow><mo>(</mo>{}<mo>)</mo></mrow>", text)
+}
+
+fn mathml_number(n: &Number, format: &NumberFormat) -> (String, u8) {
+    let magnitude = match n {
+        Number::Rational(r) if format.fractions => {
+            let fraction = format!("<mfrac><mn>{}</mn><mn>{}</mn></mfrac>", r.numer().magnitude(), r.denom());
+            (fraction, PRODUCT)
+        }
+        _ => (format!("<mn>{}</mn>", format.number(&n.abs())), ATOM),
+    };
+    if is_negative(n) {
+        (format!("<mrow><mo>-</mo>{}</mrow>", magnitude.0), SUM)
+    } else {
+        magnitude
+    }
+}
+
+fn mathml(expr: &Expr, format: &NumberFormat) -> (String, u8) {
+    match expr {
+        Expr::Number(n) => mathml_number(n, format),
+        Expr::Var(name) => (mathml_name(name), ATOM),
+        Expr::Call(name, args) => {
+            let rendered: Vec<String> = args.iter().map(|a| mathml(a, format).0).collect();
+            let text = match (name.as_str(), rendered.as_slice()) {
+                ("sqrt", [a]) => format!("<msqrt>{}</msqrt>", a),
+                ("cbrt", [a]) => format!("<mroot>{}<mn>3</mn></mroot>", a),
+                ("abs", [a]) => format!("<mrow><mo>|</mo>{}<mo>|</mo></mrow>", a),
+                ("fact", [_]) => format!("<mrow>{}<mo>!</mo></mrow>", mathml_wrap(&args[0], format, ATOM)),
+                _ => {
+                    let operator = match OPERATOR_NAMES.iter().find(|(n, _, _)| n == name) {
+                        Some((_, _, text)) => format!("<mi>{}</mi>", text),
+                        None => mathml_name(name),
+                    };
+                    // U+2061 is the invisible function application operator
+                    format!("<mrow>{}<mo>&#x2061;</mo>{}</mrow>", operator, parenthesized(&rendered.join("<mo>,</mo>")))
+                }
+            };
+            (text, ATOM)
+        }
+        Expr::List(items) => {
+            let items: Vec<String> = items.iter().map(|a| mathml(a, format).0).collect();
+            (format!("<mrow><mo>[</mo>{}<mo>]</mo></mrow>", items.join("<mo>,</mo>")), ATOM)
+        }
+        Expr::Pow(base, exp) => (format!("<msup>{}{}</msup>", mathml_wrap(base, format, ATOM), mathml(exp, format).0), POWER),
+        Expr::Neg(inner) => (format!("<mrow><mo>-</mo>{}</mrow>", mathml_wrap(inner, format, PRODUCT)), SUM),
+        Expr::Mul(factors) => {
+            let mut text = String::new();
+            for (i, factor) in factors.iter().enumerate() {
+                let factor = mathml_wrap(factor, format, PRODUCT);
+                // a visible dot between two numbers, U+2062 (invisible times) everywhere else
+                if i > 0 {
+                    let number = factor.starts_with("<mn>") || factor.starts_with("<mfrac><mn>");
+                    text.push_str(if number { "<mo>&#x22C5;</mo>" } else { "<mo>&#x2062;</mo>" });
+                }
+                text.push_str(&factor);
+            }
+            (format!("<mrow>{}</mrow>", text), PRODUCT)
+        }
+        Expr::Div(a, b) => (format!("<mfrac>{}{}</mfrac>", mathml(a, format).0, mathml(b, format).0), PRODUCT),
+        Expr::Sub(a, b) => (
+            format!("<mrow>{}<mo>-</mo>{}</mrow>", mathml_wrap(a, format, SUM), mathml_wrap(b, format, PRODUCT)),
+            SUM,
+        ),
+        Expr::Add(terms) => {
+            let mut text = String::new();
+            for (i, term) in terms.iter().enumerate() {
+                if i == 0 {
+                    text.push_str(&mathml_wrap(term, format, SUM));
+                    continue;
+                }
+                match term {
+                    Expr::Neg(inner) => text.push_str(&format!("<mo>-</mo>{}", mathml_wrap(inner, format, PRODUCT))),
+                    Expr::Number(n) if is_negative(n) => {
+                        text.push_str(&format!("<mo>-</mo>{}", mathml_wrap(&Expr::Number(-n.clone()), format, PRODUCT)))
+                    }
+                    _ => text.push_str(&format!("<mo>+</mo>{}", mathml_wrap(term, format, SUM))),
+                }
+            }
+            (format!("<mrow>{}</mrow>", text), SUM)
+        }
+        Expr::Equation(a, b) => (
+            format!("<mrow>{}<mo>=</mo>{}</mrow>", mathml_wrap(a, format, SUM), mathml_wrap(b, format, SUM)),
+            EQUATION,
+        ),
+        Expr::Inequality(a, relation, b) => {
+            let symbol = match relation {
+                Relation::Less => "&lt;",
+                Relation::LessEqual => "&#x2264;",
+                Relation::Greater => "&gt;",
+                Relation::GreaterEqual => "&#x2265;",
+            };
+            (format!("<mrow>{}<mo>{}</mo>{}</mrow>", mathml_wrap(a, format, SUM), symbol, mathml_wrap(b, format, SUM)), EQUATION)
+        }
+    }
+}
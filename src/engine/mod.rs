@@ -1,13 +1,28 @@
-pub mod engine;
-pub mod errors;
+#[allow(clippy::module_inception)]
+mod engine;
+mod errors;
+mod derivative;
+mod diagnostic;
 mod lexer;
 mod evaluator;
+mod functions;
+mod format;
+mod markup;
+mod number;
 mod parser;
+mod plot;
+mod session;
+mod simplify;
+mod table;
+mod solver;
 
-pub use engine::CalculatorEngine;
+pub use engine::{CalculatorEngine, HistoryEntry, Value};
 pub use errors::CalcError;
-use engine::Value;
-use lexer::Token;
-use lexer::tokenize;
-use evaluator::eval_ast;
-use parser::Expr;
\ No newline at end of file
+pub use diagnostic::{Diagnostic, Span};
+pub use format::NumberFormat;
+pub use number::Number;
+pub use lexer::{Token, TokenKind, tokenize};
+pub use parser::{Expr, Relation, parse};
+pub use plot::{Curve, Graph, Implicit};
+pub use table::{Table, TableRange};
+use evaluator::eval_ast;
\ No newline at end of file
@@ -0,0 +1,305 @@
+use crate::engine::CalcError;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_integer::Integer;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
+
+// exact powers whose result would need more bits than this are an error, a float could only
+// hold them as inf
+const MAX_EXACT_BITS: u64 = 1 << 20;
+
+// numeric tower of the engine: whole numbers are unbounded Integers, fractions are Rationals
+// (never with denominator 1), Float only shows up once an irrational function, a non integer
+// power or float() forces it
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum Number {
+    Integer(BigInt),
+    Rational(BigRational),
+    Float(f64),
+}
+
+impl Number {
+    pub fn zero() -> Self {
+        Number::Integer(BigInt::zero())
+    }
+
+    pub fn one() -> Self {
+        Number::Integer(BigInt::one())
+    }
+
+    // keeps the invariant that a Rational is never a whole number
+    pub fn from_rational(r: BigRational) -> Self {
+        if r.is_integer() {
+            Number::Integer(r.to_integer())
+        } else {
+            Number::Rational(r)
+        }
+    }
+
+    pub fn to_rational(&self) -> Option<BigRational> {
+        match self {
+            Number::Integer(i) => Some(BigRational::from_integer(i.clone())),
+            Number::Rational(r) => Some(r.clone()),
+            Number::Float(_) => None,
+        }
+    }
+
+    // whole numbers, including floats without a fractional part
+    pub fn to_integer(&self) -> Option<BigInt> {
+        match self {
+            Number::Integer(i) => Some(i.clone()),
+            Number::Rational(_) => None,
+            Number::Float(f) if f.is_finite() && f.fract() == 0.0 => BigInt::from_f64(*f),
+            Number::Float(_) => None,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Integer(i) => i.is_zero(),
+            Number::Rational(r) => r.is_zero(),
+            Number::Float(f) => *f == 0.0,
+        }
+    }
+
+    pub fn is_one(&self) -> bool {
+        match self {
+            Number::Integer(i) => i.is_one(),
+            Number::Rational(r) => r.is_one(),
+            Number::Float(f) => *f == 1.0,
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        !matches!(self, Number::Float(_))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Integer(i) => i.to_f64().unwrap_or(f64::NAN),
+            Number::Rational(r) => r.to_f64().unwrap_or(f64::NAN),
+            Number::Float(f) => *f,
+        }
+    }
+
+    pub fn to_float(&self) -> Number {
+        Number::Float(self.to_f64())
+    }
+
+    pub fn abs(&self) -> Number {
+        match self {
+            Number::Integer(i) => Number::Integer(i.abs()),
+            Number::Rational(r) => Number::Rational(r.abs()),
+            Number::Float(f) => Number::Float(f.abs()),
+        }
+    }
+
+    pub fn floor(&self) -> Number {
+        match self {
+            Number::Integer(_) => self.clone(),
+            Number::Rational(r) => Number::from_rational(r.floor()),
+            Number::Float(f) => Number::Float(f.floor()),
+        }
+    }
+
+    pub fn ceil(&self) -> Number {
+        match self {
+            Number::Integer(_) => self.clone(),
+            Number::Rational(r) => Number::from_rational(r.ceil()),
+            Number::Float(f) => Number::Float(f.ceil()),
+        }
+    }
+
+    pub fn round(&self) -> Number {
+        match self {
+            Number::Integer(_) => self.clone(),
+            Number::Rational(r) => Number::from_rational(r.round()),
+            Number::Float(f) => Number::Float(f.round()),
+        }
+    }
+
+    pub fn checked_div(&self, rhs: &Number) -> Result<Number, CalcError> {
+        if rhs.is_zero() {
+            return Err(CalcError::DivisionByZero);
+        }
+        match (self.to_rational(), rhs.to_rational()) {
+            (Some(a), Some(b)) => Ok(Number::from_rational(a / b)),
+            _ => Ok(Number::Float(self.to_f64() / rhs.to_f64())),
+        }
+    }
+
+    pub fn pow(&self, exponent: &Number) -> Result<Number, CalcError> {
+        if let (Some(base), Number::Integer(exp)) = (self.to_rational(), exponent) {
+            if exp.is_negative() && base.is_zero() {
+                return Err(CalcError::DivisionByZero);
+            }
+            // 0, 1 and -1 stay small for any exponent
+            if base.is_zero() || (base.denom().is_one() && base.numer().magnitude().is_one()) {
+                let flips_sign = base.is_negative() && exp.is_odd();
+                let magnitude = if base.is_zero() && !exp.is_zero() { BigRational::zero() } else { BigRational::one() };
+                return Ok(Number::from_rational(if flips_sign { -magnitude } else { magnitude }));
+            }
+            let bits = if base.is_integer() { base.numer().bits() } else { base.numer().bits() + base.denom().bits() };
+            return match exp.magnitude().to_u64().filter(|e| bits.saturating_mul(*e) <= MAX_EXACT_BITS) {
+                Some(e) => {
+                    let power = BigRational::new(base.numer().pow(e as u32), base.denom().pow(e as u32));
+                    Ok(Number::from_rational(if exp.is_negative() { power.recip() } else { power }))
+                }
+                None => Err(CalcError::DomainError(format!("{} ^ {} has too many digits to calculate exactly", self, exponent))),
+            };
+        }
+        let base = self.to_f64();
+        let exp = exponent.to_f64();
+        let result = base.powf(exp);
+        if result.is_nan() && !base.is_nan() && !exp.is_nan() {
+            return Err(CalcError::DomainError(format!("{} ^ {}", self, exponent)));
+        }
+        Ok(Number::Float(result))
+    }
+
+    // exact square root for perfect squares like 9/4, None if the result would be irrational
+    pub fn exact_sqrt(&self) -> Option<Number> {
+        match self.to_rational() {
+            Some(r) if !r.is_negative() => {
+                let numer = r.numer().sqrt();
+                let denom = r.denom().sqrt();
+                if &(&numer * &numer) == r.numer() && &(&denom * &denom) == r.denom() {
+                    Some(Number::from_rational(BigRational::new(numer, denom)))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // shared shape of + and *: Integer with Integer stays Integer, exact with exact stays exact
+    fn combine(self, rhs: Number, int_op: fn(BigInt, BigInt) -> BigInt, rational_op: fn(BigRational, BigRational) -> BigRational, float_op: fn(f64, f64) -> f64) -> Number {
+        match (self, rhs) {
+            (Number::Integer(a), Number::Integer(b)) => Number::Integer(int_op(a, b)),
+            (a, b) => match (a.to_rational(), b.to_rational()) {
+                (Some(a), Some(b)) => Number::from_rational(rational_op(a, b)),
+                _ => Number::Float(float_op(a.to_f64(), b.to_f64())),
+            },
+        }
+    }
+}
+
+impl From<i64> for Number {
+    fn from(n: i64) -> Self {
+        Number::Integer(BigInt::from(n))
+    }
+}
+
+impl From<BigInt> for Number {
+    fn from(n: BigInt) -> Self {
+        Number::Integer(n)
+    }
+}
+
+impl From<f64> for Number {
+    fn from(f: f64) -> Self {
+        Number::Float(f)
+    }
+}
+
+// decimal literals like "0.1" are read exactly as 1/10
+impl FromStr for Number {
+    type Err = CalcError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (int_part, frac_part) = s.split_once('.').unwrap_or((s, ""));
+        let valid = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if (int_part.is_empty() && frac_part.is_empty()) || !valid(int_part) || !valid(frac_part) {
+            return Err(CalcError::InvalidExpression(s.to_string()));
+        }
+
+        let digits = format!("{}{}", int_part, frac_part);
+        let numer = BigInt::from_str(&digits).map_err(|_| CalcError::InvalidExpression(s.to_string()))?;
+        let denom = num_traits::pow(BigInt::from(10), frac_part.len());
+        Ok(Number::from_rational(BigRational::new(numer, denom)))
+    }
+}
+
+impl Add for Number {
+    type Output = Number;
+
+    fn add(self, rhs: Number) -> Number {
+        self.combine(rhs, |a, b| a + b, |a, b| a + b, |a, b| a + b)
+    }
+}
+
+impl Sub for Number {
+    type Output = Number;
+
+    fn sub(self, rhs: Number) -> Number {
+        self + (-rhs)
+    }
+}
+
+impl Mul for Number {
+    type Output = Number;
+
+    fn mul(self, rhs: Number) -> Number {
+        self.combine(rhs, |a, b| a * b, |a, b| a * b, |a, b| a * b)
+    }
+}
+
+impl Neg for Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        match self {
+            Number::Integer(i) => Number::Integer(-i),
+            Number::Rational(r) => Number::Rational(-r),
+            Number::Float(f) => Number::Float(-f),
+        }
+    }
+}
+
+// exact comparison between exact numbers, anything involving a float is compared as floats
+impl PartialEq for Number {
+    fn eq(&self, other: &Number) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Integer(a), Number::Integer(b)) => Some(a.cmp(b)),
+            (a, b) => match (a.to_rational(), b.to_rational()) {
+                (Some(a), Some(b)) => Some(a.cmp(&b)),
+                _ => a.to_f64().partial_cmp(&b.to_f64()),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Integer(i) => write!(f, "{}", i),
+            Number::Rational(r) => write!(f, "{}/{}", r.numer(), r.denom()),
+            // inf or NaN would read back as a name, these cannot be mistaken for one
+            Number::Float(x) if x.is_nan() => write!(f, "not a number"),
+            Number::Float(x) if x.is_infinite() => write!(f, "{}∞", if x.is_sign_negative() { "-" } else { "" }),
+            Number::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl fmt::Debug for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Integer(_) => write!(f, "Integer({})", self),
+            Number::Rational(_) => write!(f, "Rational({})", self),
+            Number::Float(_) => write!(f, "Float({})", self),
+        }
+    }
+}
@@ -1,152 +1,531 @@
 use crate::engine::CalcError;
-use crate::engine::Token;
+use crate::engine::{Token, TokenKind};
+use crate::engine::diagnostic::{Diagnostic, ExprSpans, Span};
+use crate::engine::tokenize;
+use crate::engine::Number;
+use crate::engine::evaluator::MAX_DEPTH;
+use std::collections::BTreeSet;
+use std::fmt;
 
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Expr{
-    Number(f64),
+    Number(Number),
     Var(String),
     Add(Vec<Expr>),
     Sub(Box<Expr>, Box<Expr>),
     Mul(Vec<Expr>),
     Div(Box<Expr>, Box<Expr>),
     Pow(Box<Expr>, Box<Expr>),
-    Neg(Box<Expr>)
+    Neg(Box<Expr>),
+    Call(String, Vec<Expr>),
+    Equation(Box<Expr>, Box<Expr>), // lhs == rhs nested in an expression, only meaningful inside solve
+    Inequality(Box<Expr>, Relation, Box<Expr>), // lhs < rhs and friends, compared at the top level or plotted as a region
+    List(Vec<Expr>), // several results at once, like the solutions of an equation
 }
 
-fn precedence(token: &Token) -> i32 {
-    match token {
-        Token::Plus | Token::Minus => 1,
-        Token::Star | Token::Slash => 2,
-        Token::UnaryMinus => 3,
-        Token::Power => 4,
-        _ => 0,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Relation {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Relation {
+    pub fn holds<T: PartialOrd>(self, a: &T, b: &T) -> bool {
+        match self {
+            Relation::Less => a < b,
+            Relation::LessEqual => a <= b,
+            Relation::Greater => a > b,
+            Relation::GreaterEqual => a >= b,
+        }
     }
 }
 
-fn is_operator(token: &Token) -> bool {
-    match token {
-        Token::Plus | Token::Minus | Token::Star | Token::Slash | Token::Power  => true,
-        _ => false,
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Relation::Less => "<",
+            Relation::LessEqual => "<=",
+            Relation::Greater => ">",
+            Relation::GreaterEqual => ">=",
+        })
     }
 }
 
-fn is_unary(prev: Option<&Token>, current: &Token) -> bool {
-    match current {
-        Token::Minus => {
-            match prev {
-                None => true,
-                Some(Token::LParen)
-                | Some(Token::Plus)
-                | Some(Token::Minus)
-                | Some(Token::Star)
-                | Some(Token::Slash) => true,
-                _ => false,
-            }
-        }
-        _ => false,
+impl From<Number> for Expr {
+    fn from(n: Number) -> Self {
+        Expr::Number(n)
     }
 }
 
-fn apply_op(op: Token, stack: &mut Vec<Expr>) -> Result<(), CalcError> {
-    match op {
-        Token::Plus => {
-            let b = stack.pop().ok_or(CalcError::MissingOperand)?;
-            let a = stack.pop().ok_or(CalcError::MissingOperand)?;
-            stack.push(Expr::Add(vec![a, b]));
-        }
+impl From<i64> for Expr {
+    fn from(n: i64) -> Self {
+        Expr::Number(Number::from(n))
+    }
+}
 
-        Token::Minus => {
-            let b = stack.pop().ok_or(CalcError::MissingOperand)?;
-            let a = stack.pop().ok_or(CalcError::MissingOperand)?;
-            stack.push(Expr::Sub(Box::new(a), Box::new(b)));
-        }
+impl From<f64> for Expr {
+    fn from(f: f64) -> Self {
+        Expr::Number(Number::from(f))
+    }
+}
 
-        Token::Star => {
-            let b = stack.pop().ok_or(CalcError::MissingOperand)?;
-            let a = stack.pop().ok_or(CalcError::MissingOperand)?;
-            stack.push(Expr::Mul(vec![a, b]));
+impl Expr {
+    // direct subexpressions, in the order ExprSpans keeps their spans
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Number(_) | Expr::Var(_) => Vec::new(),
+            Expr::Add(items) | Expr::Mul(items) | Expr::Call(_, items) | Expr::List(items) => items.iter().collect(),
+            Expr::Sub(a, b) | Expr::Div(a, b) | Expr::Pow(a, b) | Expr::Equation(a, b) | Expr::Inequality(a, _, b) => vec![&**a, &**b],
+            Expr::Neg(a) => vec![&**a],
         }
+    }
 
-        Token::Slash => {
-            let b = stack.pop().ok_or(CalcError::MissingOperand)?;
-            let a = stack.pop().ok_or(CalcError::MissingOperand)?;
-            stack.push(Expr::Div(Box::new(a), Box::new(b)));
-        }
+    // copy of the expression with the variables f gives an expression for replaced
+    pub fn replace_vars<E>(&self, f: &mut impl FnMut(&str) -> Result<Option<Expr>, E>) -> Result<Expr, E> {
+        let mut all = |items: &[Expr]| items.iter().map(|e| e.replace_vars(f)).collect::<Result<Vec<_>, E>>();
+        Ok(match self {
+            Expr::Var(name) => f(name)?.unwrap_or_else(|| self.clone()),
+            Expr::Number(_) => self.clone(),
+            Expr::Add(items) => Expr::Add(all(items)?),
+            Expr::Mul(items) => Expr::Mul(all(items)?),
+            Expr::List(items) => Expr::List(all(items)?),
+            Expr::Call(name, args) => Expr::Call(name.clone(), all(args)?),
+            Expr::Neg(a) => Expr::Neg(Box::new(a.replace_vars(f)?)),
+            Expr::Inequality(a, relation, b) => Expr::Inequality(Box::new(a.replace_vars(f)?), *relation, Box::new(b.replace_vars(f)?)),
+            Expr::Sub(a, b) | Expr::Div(a, b) | Expr::Pow(a, b) | Expr::Equation(a, b) => {
+                let (a, b) = (Box::new(a.replace_vars(f)?), Box::new(b.replace_vars(f)?));
+                match self {
+                    Expr::Sub(..) => Expr::Sub(a, b),
+                    Expr::Div(..) => Expr::Div(a, b),
+                    Expr::Pow(..) => Expr::Pow(a, b),
+                    _ => Expr::Equation(a, b),
+                }
+            }
+        })
+    }
 
-        Token::Power =>{
-            let b = stack.pop().ok_or(CalcError::MissingOperand)?;
-            let a = stack.pop().ok_or(CalcError::MissingOperand)?;
-            stack.push(Expr::Pow(Box::new(a), Box::new(b)));
+    // names of all variables the expression mentions, sorted
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            if let Expr::Var(name) = expr {
+                names.insert(name.clone());
+            }
+            pending.extend(expr.children());
         }
+        names
+    }
 
-        Token::UnaryMinus => {
-            let a = stack.pop().ok_or(CalcError::MissingOperand)?;
-            stack.push(Expr::Neg(Box::new(a)));
+    // names of the functions called anywhere in the expression
+    pub fn calls(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            if let Expr::Call(name, _) = expr {
+                names.insert(name.clone());
+            }
+            pending.extend(expr.children());
         }
+        names
+    }
+}
+
+// one statement of the input, statements are separated by ';'
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    Expression(Expr, ExprSpans),
+    Assign { name: String, value: Expr, spans: ExprSpans },
+    // x := value, recomputed whenever a variable in value changes
+    Bind { name: String, value: Expr, spans: ExprSpans },
+    // x += value and friends, op is the compound token, span covers the whole statement
+    Compound { name: String, op: TokenKind, value: Expr, spans: ExprSpans, span: Span },
+    Define { name: String, name_span: Span, params: Vec<String>, body: Expr },
+    // save "file" and load "file", span covers the whole command
+    Save { path: String, span: Span },
+    Load { path: String, span: Span },
+    // commands, a name followed by names: undo, redo, vars, inspect x, unset x y, rename x y, reset
+    Undo,
+    Redo,
+    Vars,
+    Inspect { name: String, span: Span },
+    Unset { names: Vec<String>, span: Span },
+    Rename { from: String, to: String, span: Span },
+    Reset,
+}
 
-        _ => return Err(CalcError::InvalidToken("apply_op".into())),
+// binding powers as (left, right), the side with the higher number binds tighter, so a right
+// power below the left one makes ^ right associative: 2^3^2 is 2^(3^2)
+fn infix_power(kind: &TokenKind) -> Option<(u8, u8)> {
+    match kind {
+        TokenKind::Equal | TokenKind::Less | TokenKind::LessEqual | TokenKind::Greater | TokenKind::GreaterEqual => Some((1, 2)),
+        TokenKind::Plus | TokenKind::Minus => Some((3, 4)),
+        TokenKind::Star | TokenKind::Slash => Some((5, 6)),
+        TokenKind::Power => Some((10, 9)),
+        _ => None,
     }
+}
+
+// prefix - binds looser than ^ so -x^2 is -(x^2), but 2^-x still works
+const PREFIX_POWER: u8 = 7;
+const POSTFIX_POWER: u8 = 11;
+
+fn starts_operand(kind: &TokenKind) -> bool {
+    matches!(kind, TokenKind::Number(_) | TokenKind::Var(_) | TokenKind::Func(_) | TokenKind::LParen)
+}
 
-    Ok(())
+fn invalid(message: String, span: Span) -> Diagnostic {
+    CalcError::InvalidExpression(message).at(span)
 }
 
-pub fn parse_to_ast(tokens: &Vec<Token>) -> Result<Expr, CalcError> {
-    let mut expr_stack: Vec<Expr> = Vec::new();
-    let mut ops: Vec<Token> = Vec::new();
-    let mut prev: Option<&Token> = None;
+// operands with the spans of their tree
+type Operand = (Expr, ExprSpans);
 
-    for token in tokens {
-        match token {
-            Token::Number(n) => expr_stack.push(Expr::Number(*n)),
-            Token::Var(v)    => expr_stack.push(Expr::Var(v.clone())),
-            op if is_unary(prev, op) => {
-                ops.push(Token::UnaryMinus);
-            },
-            op if is_operator(op) => {
-                while let Some(top) = ops.last() {
-                    if precedence(top) >= precedence(op) {
-                        let op = ops.pop().unwrap();
-                        apply_op(op, &mut expr_stack)?;
-                    } else {
-                        break;
-                    }
-                }
-                ops.push(op.clone());
-            },
+fn binary(op: &TokenKind, (a, a_spans): Operand, (b, b_spans): Operand) -> Operand {
+    let expr = match op {
+        // a + b + c is kept as one sum and a * b * c as one product so long chains do not nest deeply
+        TokenKind::Plus => match a {
+            Expr::Add(mut terms) => {
+                terms.push(b);
+                let mut children = a_spans.children;
+                let span = a_spans.span.to(b_spans.span);
+                children.push(b_spans);
+                return (Expr::Add(terms), ExprSpans { span, children });
+            }
+            a => Expr::Add(vec![a, b]),
+        },
+        TokenKind::Minus => Expr::Sub(Box::new(a), Box::new(b)),
+        TokenKind::Star => match a {
+            Expr::Mul(mut factors) => {
+                factors.push(b);
+                let mut children = a_spans.children;
+                let span = a_spans.span.to(b_spans.span);
+                children.push(b_spans);
+                return (Expr::Mul(factors), ExprSpans { span, children });
+            }
+            a => Expr::Mul(vec![a, b]),
+        },
+        TokenKind::Slash => Expr::Div(Box::new(a), Box::new(b)),
+        TokenKind::Power => Expr::Pow(Box::new(a), Box::new(b)),
+        TokenKind::Less => Expr::Inequality(Box::new(a), Relation::Less, Box::new(b)),
+        TokenKind::LessEqual => Expr::Inequality(Box::new(a), Relation::LessEqual, Box::new(b)),
+        TokenKind::Greater => Expr::Inequality(Box::new(a), Relation::Greater, Box::new(b)),
+        TokenKind::GreaterEqual => Expr::Inequality(Box::new(a), Relation::GreaterEqual, Box::new(b)),
+        _ => Expr::Equation(Box::new(a), Box::new(b)),
+    };
+    let span = a_spans.span.to(b_spans.span);
+    (expr, ExprSpans { span, children: vec![a_spans, b_spans] })
+}
 
-            Token::LParen => {
-                ops.push(Token::LParen);
-            },
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    depth: usize, // nested expression calls, limited so deep parentheses cannot overflow the stack
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
 
-            Token::RParen => {
-                while let Some(op) = ops.pop() {
-                    if op == Token::LParen { break; }
-                    apply_op(op, &mut expr_stack)?;
+    fn peek_kind(&self, ahead: usize) -> Option<&'a TokenKind> {
+        self.tokens.get(self.pos + ahead).map(|t| &t.kind)
+    }
+
+    fn advance(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    // where "the end" is for errors about missing input, right after the last token
+    fn end_span(&self) -> Span {
+        let end = self.tokens.last().map_or(0, |t| t.span.end);
+        Span::new(end, end)
+    }
+
+    fn expect_end(&self, lhs: &ExprSpans) -> Result<(), Diagnostic> {
+        let Some(token) = self.peek() else {
+            return Ok(());
+        };
+        Err(match token.kind {
+            TokenKind::RParen => invalid("Unmatched ')'".into(), token.span),
+            TokenKind::Comma => invalid("',' outside of a function call".into(), token.span),
+            TokenKind::Assign | TokenKind::Bind | TokenKind::PlusEqual | TokenKind::MinusEqual | TokenKind::StarEqual | TokenKind::SlashEqual => {
+                invalid("Cannot assign to a non variable expression".into(), lhs.span)
+            }
+            _ => invalid(format!("Unexpected {}", token.kind), token.span),
+        })
+    }
+
+    fn statement(&mut self) -> Result<Statement, Diagnostic> {
+        if let Some(command) = self.command() {
+            self.pos = self.tokens.len();
+            return Ok(command);
+        }
+        let first = self.peek().expect("statement() needs a token");
+        match (&first.kind, self.peek_kind(1)) {
+            (TokenKind::Var(name), Some(TokenKind::Assign)) => {
+                self.pos += 2;
+                let (value, spans) = self.value_after(&self.tokens[self.pos - 1])?;
+                Ok(Statement::Assign { name: name.clone(), value, spans })
+            }
+            (TokenKind::Var(command), Some(TokenKind::Text(path))) if command == "save" || command == "load" => {
+                let span = first.span.to(self.tokens[self.pos + 1].span);
+                self.pos += 2;
+                if let Some(token) = self.peek() {
+                    return Err(invalid(format!("Unexpected {} after the file name", token.kind), token.span));
                 }
-            },
+                let path = path.clone();
+                Ok(if command == "save" { Statement::Save { path, span } } else { Statement::Load { path, span } })
+            }
+            (TokenKind::Var(name), Some(TokenKind::Bind)) => {
+                self.pos += 2;
+                let (value, spans) = self.value_after(&self.tokens[self.pos - 1])?;
+                Ok(Statement::Bind { name: name.clone(), value, spans })
+            }
+            (TokenKind::Var(name), Some(op @ (TokenKind::PlusEqual | TokenKind::MinusEqual | TokenKind::StarEqual | TokenKind::SlashEqual))) => {
+                self.pos += 2;
+                let (value, spans) = self.value_after(&self.tokens[self.pos - 1])?;
+                let span = first.span.to(spans.span);
+                Ok(Statement::Compound { name: name.clone(), op: op.clone(), value, spans, span })
+            }
+            (TokenKind::Func(name), _) if self.is_definition() => {
+                self.pos += 1;
+                let params = self.parameters(name)?;
+                self.pos += 1; // the "="
+                let (body, _) = self.value_after(&self.tokens[self.pos - 1])?;
+                Ok(Statement::Define { name: name.clone(), name_span: first.span, params, body })
+            }
+            _ => {
+                let (expr, spans) = self.expression(0, None)?;
+                self.expect_end(&spans)?;
+                Ok(Statement::Expression(expr, spans))
+            }
+        }
+    }
+
+    // a statement of only names that starts with a command word, anything else like "vars + 1"
+    // is an expression that happens to use a variable with that name
+    fn command(&self) -> Option<Statement> {
+        let words = self.tokens
+            .iter()
+            .map(|t| match &t.kind {
+                TokenKind::Var(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect::<Option<Vec<String>>>()?;
+        let span = self.tokens.first()?.span.to(self.tokens.last()?.span);
+        let command = match words.as_slice() {
+            [command] if command == "undo" => Statement::Undo,
+            [command] if command == "redo" => Statement::Redo,
+            [command] if command == "vars" => Statement::Vars,
+            [command] if command == "reset" => Statement::Reset,
+            [command, name] if command == "inspect" => Statement::Inspect { name: name.clone(), span },
+            [command, names @ ..] if command == "unset" && !names.is_empty() => Statement::Unset { names: names.to_vec(), span },
+            [command, from, to] if command == "rename" => Statement::Rename { from: from.clone(), to: to.clone(), span },
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    // f(...) = ... when the parentheses after the name are directly followed by "="
+    fn is_definition(&self) -> bool {
+        let mut depth = 0;
+        for (i, token) in self.tokens[self.pos + 1..].iter().enumerate() {
+            match token.kind {
+                TokenKind::LParen => depth += 1,
+                TokenKind::RParen => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return self.peek_kind(i + 2) == Some(&TokenKind::Assign);
+            }
+        }
+        false
+    }
+
+    // "(x, y)" of a definition, the position is on the "("
+    fn parameters(&mut self, name: &str) -> Result<Vec<String>, Diagnostic> {
+        self.advance();
+        let mut params: Vec<String> = Vec::new();
+        if self.peek_kind(0) == Some(&TokenKind::RParen) {
+            self.advance();
+            return Ok(params);
+        }
+        loop {
+            let token = self.advance().expect("is_definition found the closing ')'");
+            match &token.kind {
+                TokenKind::Var(p) if !params.contains(p) => params.push(p.clone()),
+                TokenKind::Var(p) => return Err(invalid(format!("Parameter {} appears twice in definition of {}", p, name), token.span)),
+                _ => return Err(invalid(format!("Parameters of {} have to be plain names", name), token.span)),
+            }
+            let separator = self.advance().expect("is_definition found the closing ')'");
+            match separator.kind {
+                TokenKind::Comma => continue,
+                TokenKind::RParen => return Ok(params),
+                _ => return Err(invalid(format!("Parameters of {} have to be plain names", name), separator.span)),
+            }
+        }
+    }
+
+    // the whole rest of the statement as the value of an assignment or definition
+    fn value_after(&mut self, operator: &Token) -> Result<Operand, Diagnostic> {
+        if self.peek().is_none() {
+            return Err(invalid(format!("Expected a value after {}", operator.kind), operator.span));
+        }
+        let value = self.expression(0, None)?;
+        self.expect_end(&value.1)?;
+        Ok(value)
+    }
 
-            a => {
-                return Err(CalcError::InvalidToken(
-                    format!("Unexpected token {:?} in expression",a)
-                ));
+    // everything up to the first operator binding looser than min_power, `after` is the operator
+    // that needs this expression, for the message when it is missing
+    fn expression(&mut self, min_power: u8, after: Option<&'a Token>) -> Result<Operand, Diagnostic> {
+        if self.depth == MAX_DEPTH {
+            let span = self.peek().map_or(self.end_span(), |t| t.span);
+            return Err(CalcError::RecursionLimit(MAX_DEPTH).at(span));
+        }
+        self.depth += 1;
+        let result = self.operators(min_power, after);
+        self.depth -= 1;
+        result
+    }
+
+    fn operators(&mut self, min_power: u8, after: Option<&'a Token>) -> Result<Operand, Diagnostic> {
+        let mut lhs = self.operand(after)?;
+        while let Some(token) = self.peek() {
+            if token.kind == TokenKind::Factorial {
+                if POSTFIX_POWER < min_power {
+                    break;
+                }
+                self.advance();
+                let span = lhs.1.span.to(token.span);
+                lhs = (Expr::Call("fact".into(), vec![lhs.0]), ExprSpans { span, children: vec![lhs.1] });
+                continue;
+            }
+            let Some((left, right)) = infix_power(&token.kind) else {
+                if starts_operand(&token.kind) {
+                    return Err(invalid("Expected an operator between two values".into(), token.span));
+                }
+                break;
+            };
+            if left < min_power {
+                break;
             }
+            self.advance();
+            let rhs = self.expression(right, Some(token))?;
+            lhs = binary(&token.kind, lhs, rhs);
         }
-        prev = Some(token);
+        Ok(lhs)
     }
 
-    while let Some(op) = ops.pop() {
-        if matches!(op, Token::LParen | Token::RParen) {
-            return Err(CalcError::InvalidExpression("Mismatched parentheses".into()));
+    // a single value: number, name, call, parenthesized expression or prefix operator applied to one
+    fn operand(&mut self, after: Option<&'a Token>) -> Result<Operand, Diagnostic> {
+        let Some(token) = self.advance() else {
+            return Err(match after {
+                Some(op) => invalid(format!("Expected a value after {}", op.kind), op.span),
+                None => Diagnostic { error: CalcError::EmptyExpression, span: Some(self.end_span()) },
+            });
+        };
+        match &token.kind {
+            TokenKind::Number(n) => Ok((Expr::Number(n.clone()), ExprSpans::leaf(token.span))),
+            TokenKind::Var(name) => Ok((Expr::Var(name.clone()), ExprSpans::leaf(token.span))),
+            TokenKind::Minus | TokenKind::Plus => {
+                let (inner, spans) = self.expression(PREFIX_POWER, Some(token))?;
+                let span = token.span.to(spans.span);
+                if token.kind == TokenKind::Plus {
+                    return Ok((inner, ExprSpans { span, ..spans }));
+                }
+                Ok((Expr::Neg(Box::new(inner)), ExprSpans { span, children: vec![spans] }))
+            }
+            TokenKind::LParen => {
+                if self.peek_kind(0) == Some(&TokenKind::RParen) {
+                    let span = token.span.to(self.advance().unwrap().span);
+                    return Err(invalid("Expected a value inside the parentheses".into(), span));
+                }
+                let (inner, spans) = self.expression(0, Some(token))?;
+                let close = self.close(token)?;
+                // "(a + b)" underlines with its parentheses
+                Ok((inner, ExprSpans { span: token.span.to(close), ..spans }))
+            }
+            TokenKind::Func(name) => self.call(name, token),
+            _ => Err(match after {
+                Some(op) if matches!(token.kind, TokenKind::RParen | TokenKind::Comma | TokenKind::EndOfFile) => {
+                    invalid(format!("Expected a value after {}", op.kind), op.span)
+                }
+                _ => invalid(format!("Expected a value, found {}", token.kind), token.span),
+            }),
         }
-        apply_op(op, &mut expr_stack)?;
     }
 
-    if expr_stack.len() > 1 {
-        return Err(CalcError::InvalidExpression("Can't create AST from this expression, too many operands".into()));
+    // the ")" matching open, with a message pointing at open when it is missing
+    fn close(&mut self, open: &Token) -> Result<Span, Diagnostic> {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::RParen => {
+                self.advance();
+                Ok(token.span)
+            }
+            Some(token) if token.kind == TokenKind::Comma => Err(invalid("',' outside of a function call".into(), token.span)),
+            Some(token) if starts_operand(&token.kind) => Err(invalid("Expected an operator between two values".into(), token.span)),
+            Some(token) => Err(invalid(format!("Unexpected {}", token.kind), token.span)),
+            None => Err(invalid("Missing ')' to close this '('".into(), open.span)),
+        }
     }
-    else if expr_stack.len() == 0 {
-        return Err(CalcError::EmptyExpression);
+
+    fn call(&mut self, name: &str, name_token: &Token) -> Result<Operand, Diagnostic> {
+        // the lexer only makes a Func when a "(" follows
+        let open = self.advance().expect("Func is followed by '('");
+        let mut args: Vec<Expr> = Vec::new();
+        let mut spans: Vec<ExprSpans> = Vec::new();
+        let close = if self.peek_kind(0) == Some(&TokenKind::RParen) {
+            self.advance().unwrap().span
+        } else {
+            loop {
+                let (arg, arg_spans) = self.expression(0, Some(open))?;
+                args.push(arg);
+                spans.push(arg_spans);
+                match self.peek() {
+                    Some(token) if token.kind == TokenKind::Comma => {
+                        self.advance();
+                        if matches!(self.peek_kind(0), None | Some(TokenKind::RParen)) {
+                            return Err(invalid(format!("Expected an argument after ',' in call to {}", name), token.span));
+                        }
+                    }
+                    _ => break self.close(open)?,
+                }
+            }
+        };
+        let span = name_token.span.to(close);
+        Ok((Expr::Call(name.to_string(), args), ExprSpans { span, children: spans }))
     }
+}
 
-    Ok(expr_stack.pop().unwrap())
-}
\ No newline at end of file
+// parses every statement before any of them runs, empty statements like the one after a
+// trailing ';' are skipped. Each statement comes with the span of its source.
+pub fn parse_statements(tokens: &[Token]) -> Result<Vec<(Statement, Span)>, Diagnostic> {
+    tokens
+        .split(|t| t.kind == TokenKind::EndOfFile)
+        .filter_map(|tokens| Some((tokens, tokens.first()?.span.to(tokens.last()?.span))))
+        .map(|(tokens, span)| Ok((Parser { tokens, pos: 0, depth: 0 }.statement()?, span)))
+        .collect()
+}
+
+// parses a single expression from text, assignments and several statements go through
+// CalculatorEngine::evaluate instead
+pub fn parse(input: &str) -> Result<Expr, Diagnostic> {
+    let tokens = tokenize(input)?;
+    if let Some(t) = tokens.iter().find(|t| t.kind == TokenKind::EndOfFile) {
+        return Err(invalid("Expected a single expression without ';'".into(), t.span));
+    }
+    let mut parser = Parser { tokens: &tokens, pos: 0, depth: 0 };
+    let (expr, spans) = parser.expression(0, None)?;
+    if let Some(t) = parser.peek().filter(|t| matches!(t.kind, TokenKind::Assign | TokenKind::Bind | TokenKind::PlusEqual | TokenKind::MinusEqual | TokenKind::StarEqual | TokenKind::SlashEqual)) {
+        return Err(invalid("Assignments can only be evaluated by an engine".into(), t.span));
+    }
+    parser.expect_end(&spans)?;
+    Ok(expr)
+}
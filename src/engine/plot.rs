@@ -0,0 +1,312 @@
+use crate::engine::CalcError;
+use crate::engine::{Expr, Relation};
+use crate::engine::Number;
+use crate::engine::functions::{lookup, Kernel};
+use std::collections::{HashMap, VecDeque};
+
+// how often the interval between two samples is halved to tell a steep curve from a jump
+const BISECTIONS: usize = 24;
+
+// An expression in one variable with everything else already substituted, evaluated with plain
+// floats since a plot needs thousands of points and no exact fractions. Where it is undefined,
+// like 1/x at 0 or sqrt(x) below 0, it has no value and the plot leaves a gap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Graph {
+    pub label: String,
+    var: String,
+    expr: Expr,
+}
+
+impl Graph {
+    pub(crate) fn new(label: &str, var: &str, expr: Expr) -> Result<Graph, CalcError> {
+        if let Some(other) = expr.variables().into_iter().find(|name| name != var) {
+            return Err(CalcError::InvalidExpression(format!("{} is not defined, a graph can only depend on {}", other, var)));
+        }
+        Ok(Graph { label: label.to_string(), var: var.to_string(), expr })
+    }
+
+    pub fn at(&self, x: f64) -> Option<f64> {
+        float_value(&self.expr, &|name| (name == self.var).then_some(x)).filter(|y| y.is_finite())
+    }
+
+    // evenly spaced points from from to to, split into pieces wherever the graph has no value or
+    // jumps, so no line gets drawn across a pole or a step
+    pub fn sample(&self, from: f64, to: f64, samples: usize) -> Vec<Vec<[f64; 2]>> {
+        let step = (to - from) / samples.max(2).saturating_sub(1) as f64;
+        let mut pieces = Vec::new();
+        let mut piece: Vec<[f64; 2]> = Vec::new();
+        for i in 0..samples.max(2) {
+            let x = from + step * i as f64;
+            let Some(y) = self.at(x) else {
+                pieces.extend((!piece.is_empty()).then(|| std::mem::take(&mut piece)));
+                continue;
+            };
+            if let Some(&[last_x, last_y]) = piece.last()
+                && self.jumps(last_x, last_y, x, y)
+            {
+                pieces.push(std::mem::take(&mut piece));
+            }
+            piece.push([x, y]);
+        }
+        pieces.extend((!piece.is_empty()).then_some(piece));
+        pieces
+    }
+
+    // Follows the steeper half of the interval down. On a continuous curve the difference shrinks
+    // with the interval, at a pole it grows and at a step it stays, and a point without a value
+    // on the way is a gap as well.
+    fn jumps(&self, mut a: f64, mut ya: f64, mut b: f64, mut yb: f64) -> bool {
+        let jump = (yb - ya).abs();
+        if jump == 0.0 {
+            return false;
+        }
+        for _ in 0..BISECTIONS {
+            let m = (a + b) / 2.0;
+            let Some(ym) = self.at(m) else {
+                return true;
+            };
+            if (ym - ya).abs() >= (yb - ym).abs() {
+                (b, yb) = (m, ym);
+            } else {
+                (a, ya) = (m, ym);
+            }
+        }
+        (yb - ya).abs() > jump / 2.0
+    }
+}
+
+// a curve traced by a parameter, (x(t), y(t)) or a polar r(theta) turned into one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    pub label: String,
+    x: Graph,
+    y: Graph,
+}
+
+impl Curve {
+    pub(crate) fn parametric(label: &str, x: Graph, y: Graph) -> Curve {
+        Curve { label: label.to_string(), x, y }
+    }
+
+    // x = r*cos(theta), y = r*sin(theta)
+    pub(crate) fn polar(label: &str, r: Graph) -> Curve {
+        let along = |function: &str| Graph {
+            label: r.label.clone(),
+            var: r.var.clone(),
+            expr: Expr::Mul(vec![r.expr.clone(), Expr::Call(function.to_string(), vec![Expr::Var(r.var.clone())])]),
+        };
+        Curve { label: label.to_string(), x: along("cos"), y: along("sin") }
+    }
+
+    pub fn at(&self, t: f64) -> Option<[f64; 2]> {
+        Some([self.x.at(t)?, self.y.at(t)?])
+    }
+
+    // like Graph::sample, with a gap wherever either coordinate has none or jumps
+    pub fn sample(&self, from: f64, to: f64, samples: usize) -> Vec<Vec<[f64; 2]>> {
+        let step = (to - from) / samples.max(2).saturating_sub(1) as f64;
+        let mut pieces = Vec::new();
+        let mut piece = Vec::new();
+        let mut last: Option<(f64, [f64; 2])> = None;
+        for i in 0..samples.max(2) {
+            let t = from + step * i as f64;
+            let Some(point) = self.at(t) else {
+                pieces.extend((!piece.is_empty()).then(|| std::mem::take(&mut piece)));
+                last = None;
+                continue;
+            };
+            if let Some((last_t, [last_x, last_y])) = last
+                && (self.x.jumps(last_t, last_x, t, point[0]) || self.y.jumps(last_t, last_y, t, point[1]))
+            {
+                pieces.push(std::mem::take(&mut piece));
+            }
+            piece.push(point);
+            last = Some((t, point));
+        }
+        pieces.extend((!piece.is_empty()).then_some(piece));
+        pieces
+    }
+}
+
+// An equation or inequality in x and y. Its curve is where both sides are equal, found with
+// marching squares on a grid of the difference of the sides, and an inequality also has the
+// region where it holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Implicit {
+    pub label: String,
+    pub relation: Option<Relation>, // None for an equation
+    difference: Expr,
+}
+
+// a grid edge the curve can cross, from node (i, j) one step along x or along y
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Edge {
+    Across(usize, usize),
+    Up(usize, usize),
+}
+
+impl Implicit {
+    pub(crate) fn new(label: &str, left: Expr, relation: Option<Relation>, right: Expr) -> Result<Implicit, CalcError> {
+        let difference = Expr::Sub(Box::new(left), Box::new(right));
+        if let Some(other) = difference.variables().into_iter().find(|name| name != "x" && name != "y") {
+            return Err(CalcError::InvalidExpression(format!("{} is not defined, a curve can only depend on x and y", other)));
+        }
+        Ok(Implicit { label: label.to_string(), relation, difference })
+    }
+
+    // left side minus right side
+    pub fn at(&self, x: f64, y: f64) -> Option<f64> {
+        let value = |name: &str| match name {
+            "x" => Some(x),
+            "y" => Some(y),
+            _ => None,
+        };
+        float_value(&self.difference, &value).filter(|d| d.is_finite())
+    }
+
+    // whether an inequality holds at the point, an equation has no region
+    pub fn holds(&self, x: f64, y: f64) -> bool {
+        match (self.relation, self.at(x, y)) {
+            (Some(relation), Some(difference)) => relation.holds(&difference, &0.0),
+            _ => false,
+        }
+    }
+
+    // the curve as lines through the rectangle from min to max, split into cells[0] by cells[1]
+    pub fn contour(&self, min: [f64; 2], max: [f64; 2], cells: [usize; 2]) -> Vec<Vec<[f64; 2]>> {
+        let [nx, ny] = cells.map(|n| n.max(1));
+        let step = [(max[0] - min[0]) / nx as f64, (max[1] - min[1]) / ny as f64];
+        let node = |i: usize, j: usize| [min[0] + step[0] * i as f64, min[1] + step[1] * j as f64];
+        let values: Vec<Vec<Option<f64>>> = (0..=nx)
+            .map(|i| (0..=ny).map(|j| { let [x, y] = node(i, j); self.at(x, y) }).collect())
+            .collect();
+
+        // where the curve crosses an edge, the point is placed by interpolating the two ends
+        let crossing = |edge: Edge| -> Option<[f64; 2]> {
+            let ((i0, j0), (i1, j1)) = match edge {
+                Edge::Across(i, j) => ((i, j), (i + 1, j)),
+                Edge::Up(i, j) => ((i, j), (i, j + 1)),
+            };
+            let (v0, v1) = (values[i0][j0]?, values[i1][j1]?);
+            if (v0 > 0.0) == (v1 > 0.0) {
+                return None;
+            }
+            let t = v0 / (v0 - v1);
+            let (a, b) = (node(i0, j0), node(i1, j1));
+            let point = [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t];
+            // the sign also changes across a pole like the one of 1/x, but there the difference
+            // gets larger instead of reaching zero
+            let v = self.at(point[0], point[1])?;
+            (v.abs() < v0.abs().max(v1.abs())).then_some(point)
+        };
+
+        let mut segments: Vec<(Edge, [f64; 2], Edge, [f64; 2])> = Vec::new();
+        for i in 0..nx {
+            for j in 0..ny {
+                let edges = [Edge::Across(i, j), Edge::Up(i + 1, j), Edge::Across(i, j + 1), Edge::Up(i, j)];
+                let crossed: Vec<(Edge, [f64; 2])> = edges.into_iter().filter_map(|e| Some((e, crossing(e)?))).collect();
+                match crossed[..] {
+                    [(a, pa), (b, pb)] => segments.push((a, pa, b, pb)),
+                    // a saddle, the value in the middle decides which corners are connected
+                    [bottom, right, top, left] => {
+                        let corners = [values[i][j], values[i + 1][j], values[i + 1][j + 1], values[i][j + 1]];
+                        let Some(corners) = corners.into_iter().collect::<Option<Vec<f64>>>() else {
+                            continue;
+                        };
+                        let center = corners.iter().sum::<f64>() / 4.0;
+                        let pairs = if (center > 0.0) == (corners[0] > 0.0) {
+                            [(bottom, right), (top, left)]
+                        } else {
+                            [(bottom, left), (right, top)]
+                        };
+                        segments.extend(pairs.map(|((a, pa), (b, pb))| (a, pa, b, pb)));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        join(&segments)
+    }
+
+    // rectangles as (lower left, upper right) covering the cells where the inequality holds in
+    // their middle, cells next to each other in a row are merged
+    pub fn region(&self, min: [f64; 2], max: [f64; 2], cells: [usize; 2]) -> Vec<[[f64; 2]; 2]> {
+        let [nx, ny] = cells.map(|n| n.max(1));
+        let step = [(max[0] - min[0]) / nx as f64, (max[1] - min[1]) / ny as f64];
+        let mut rectangles = Vec::new();
+        for j in 0..ny {
+            let (bottom, top) = (min[1] + step[1] * j as f64, min[1] + step[1] * (j + 1) as f64);
+            let mut start = None;
+            for i in 0..=nx {
+                let inside = i < nx && self.holds(min[0] + step[0] * (i as f64 + 0.5), (bottom + top) / 2.0);
+                match (inside, start) {
+                    (true, None) => start = Some(i),
+                    (false, Some(first)) => {
+                        rectangles.push([[min[0] + step[0] * first as f64, bottom], [min[0] + step[0] * i as f64, top]]);
+                        start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        rectangles
+    }
+}
+
+// chains segments that end on the same edge into lines, a closed curve ends where it started
+fn join(segments: &[(Edge, [f64; 2], Edge, [f64; 2])]) -> Vec<Vec<[f64; 2]>> {
+    let mut ends: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (n, (a, _, b, _)) in segments.iter().enumerate() {
+        ends.entry(*a).or_default().push(n);
+        ends.entry(*b).or_default().push(n);
+    }
+    let mut used = vec![false; segments.len()];
+    let mut lines = Vec::new();
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let (first_edge, first, last_edge, last) = segments[start];
+        let mut line = VecDeque::from([first, last]);
+        for forward in [true, false] {
+            let mut edge = if forward { last_edge } else { first_edge };
+            while let Some(next) = ends[&edge].iter().copied().find(|&n| !used[n]) {
+                used[next] = true;
+                let (a, pa, b, pb) = segments[next];
+                let (point, other) = if a == edge { (pb, b) } else { (pa, a) };
+                if forward { line.push_back(point) } else { line.push_front(point) }
+                edge = other;
+            }
+        }
+        lines.push(line.into());
+    }
+    lines
+}
+
+fn float_value(expr: &Expr, var: &impl Fn(&str) -> Option<f64>) -> Option<f64> {
+    let value = |e: &Expr| float_value(e, var);
+    match expr {
+        Expr::Number(n) => Some(n.to_f64()),
+        Expr::Var(name) => var(name),
+        Expr::Add(terms) => terms.iter().map(value).sum(),
+        Expr::Mul(factors) => factors.iter().map(value).product(),
+        Expr::Sub(a, b) => Some(value(a)? - value(b)?),
+        Expr::Div(a, b) => Some(value(a)? / value(b)?),
+        Expr::Pow(a, b) => Some(value(a)?.powf(value(b)?)),
+        Expr::Neg(a) => Some(-value(a)?),
+        Expr::Call(name, args) => {
+            let function = lookup(name).filter(|f| f.arity == args.len())?;
+            let args = args.iter().map(value).collect::<Option<Vec<f64>>>()?;
+            match &function.kernel {
+                Kernel::Float(apply) => Some(apply(&args)),
+                Kernel::Exact(apply) => {
+                    let args: Vec<Number> = args.into_iter().map(Number::Float).collect();
+                    apply(&args).ok().map(|n| n.to_f64())
+                }
+            }
+        }
+        // lists and equations have no single value to draw
+        Expr::List(_) | Expr::Equation(_, _) | Expr::Inequality(_, _, _) => None,
+    }
+}
@@ -0,0 +1,237 @@
+use crate::engine::CalcError;
+use crate::engine::Expr;
+use crate::engine::Number;
+use crate::engine::NumberFormat;
+use crate::engine::{HistoryEntry, Value};
+use crate::engine::eval_ast;
+use crate::engine::functions::{is_valid_name, UserFunction};
+use crate::engine::parse;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// bumped whenever the file layout changes in a way older versions cannot read
+const VERSION: u32 = 2;
+
+// how a list is written, see mark_floats
+const LIST: &str = "list";
+
+// Everything a session consists of. On disk expressions are kept as the text they display as,
+// so the file stays readable and can be edited by hand:
+//   {
+//     "version": 2,
+//     "settings": { "fractions": true, "precision": null },
+//     "variables": { "a": "5", "b": "6" },
+//     "bindings": { "b": "a + 1" },
+//     "functions": { "f": { "params": ["x"], "body": "x^2 + a" } },
+//     "history": [
+//       { "input": "a = 5", "result": { "value": "5" } },
+//       { "input": "a == 6", "result": { "bool": false } }
+//     ]
+//   }
+pub struct Session {
+    pub variables: HashMap<String, Expr>,
+    pub bindings: HashMap<String, Expr>,
+    pub functions: HashMap<String, UserFunction>,
+    pub history: Vec<HistoryEntry>,
+    pub number_format: NumberFormat,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SessionFile {
+    version: u32,
+    settings: Settings,
+    variables: BTreeMap<String, String>,
+    #[serde(default)]
+    bindings: BTreeMap<String, String>,
+    #[serde(default)]
+    functions: BTreeMap<String, FunctionFile>,
+    #[serde(default)]
+    history: Vec<HistoryFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum HistoryFile {
+    Entry { input: String, result: ResultFile },
+    // format 1 only kept the inputs, without results there is nothing to refer to so they are dropped
+    Input(String),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ResultFile {
+    Value(String),
+    Bool(bool),
+    Text(String),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Settings {
+    fractions: bool,
+    precision: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FunctionFile {
+    params: Vec<String>,
+    body: String,
+}
+
+impl Session {
+    pub fn to_json(&self) -> Result<String, CalcError> {
+        let mut functions = BTreeMap::new();
+        for (name, f) in &self.functions {
+            functions.insert(name.clone(), FunctionFile { params: f.params.clone(), body: source(name, &f.body)? });
+        }
+        let mut history = Vec::new();
+        for entry in &self.history {
+            // a result like inf cannot be read back as a number, it is kept as its text so $n still
+            // shows it and the rest of the session can be saved
+            let value = |expr: &Expr| match source(&entry.input, expr) {
+                Ok(text) => ResultFile::Value(text),
+                Err(_) => ResultFile::Text(entry.value.display_with(self.number_format).to_string()),
+            };
+            let result = match &entry.value {
+                Value::Number(n) => value(&Expr::Number(n.clone())),
+                Value::Expression(expr) => value(expr),
+                Value::Bool(b) => ResultFile::Bool(*b),
+                Value::Text(text) => ResultFile::Text(text.clone()),
+            };
+            history.push(HistoryFile::Entry { input: entry.input.clone(), result });
+        }
+        let file = SessionFile {
+            version: VERSION,
+            settings: Settings {
+                fractions: self.number_format.fractions,
+                precision: self.number_format.precision,
+            },
+            variables: sources(&self.variables)?,
+            bindings: sources(&self.bindings)?,
+            functions,
+            history,
+        };
+        serde_json::to_string_pretty(&file).map_err(|e| CalcError::HowDidWeGetHere(e.to_string()))
+    }
+
+    pub fn from_json(text: &str) -> Result<Session, CalcError> {
+        let file: SessionFile = serde_json::from_str(text).map_err(|e| invalid(e.to_string()))?;
+        if file.version > VERSION {
+            return Err(invalid(format!("it was written by a newer version (format {})", file.version)));
+        }
+
+        let mut functions = HashMap::new();
+        for (name, function) in file.functions {
+            if !is_valid_name(&name) || !function.params.iter().all(|p| is_valid_name(p)) {
+                return Err(invalid(format!("{}({}) is not a valid function", name, function.params.join(", "))));
+            }
+            let body = expression(&name, &function.body)?;
+            functions.insert(name, UserFunction { params: function.params, body });
+        }
+
+        // values were stored evaluated, evaluating them again turns float(...) back into floats
+        let mut variables = HashMap::new();
+        for (name, text) in &file.variables {
+            let expr = stored_value(expression(name, text)?);
+            let value = eval_ast(&expr, &HashMap::new(), &functions, &mut HashSet::new())
+                .map_err(|e| invalid(format!("{}: {}", name, e)))?;
+            variables.insert(name.clone(), value);
+        }
+        let mut bindings = HashMap::new();
+        for (name, text) in &file.bindings {
+            if !variables.contains_key(name) {
+                return Err(invalid(format!("binding {} has no value", name)));
+            }
+            bindings.insert(name.clone(), expression(name, text)?);
+        }
+
+        let mut history = Vec::new();
+        for entry in file.history {
+            let HistoryFile::Entry { input, result } = entry else {
+                continue;
+            };
+            let value = match result {
+                // evaluated only to turn float(...) back into floats. A result that cannot be
+                // evaluated on its own, like the inequality a function was defined as, is kept as
+                // it reads, and one that does not even parse as text, the file stays usable either way.
+                ResultFile::Value(text) => match parse(&text).map(stored_value) {
+                    Ok(expr) => match eval_ast(&expr, &HashMap::new(), &functions, &mut HashSet::new()) {
+                        Ok(Expr::Number(n)) => Value::Number(n),
+                        Ok(expr) => Value::Expression(expr),
+                        Err(_) => Value::Expression(expr),
+                    },
+                    Err(_) => Value::Text(text),
+                },
+                ResultFile::Bool(b) => Value::Bool(b),
+                ResultFile::Text(text) => Value::Text(text),
+            };
+            history.push(HistoryEntry { input, value });
+        }
+
+        Ok(Session {
+            variables,
+            bindings,
+            functions,
+            history,
+            number_format: NumberFormat {
+                fractions: file.settings.fractions,
+                precision: file.settings.precision,
+            },
+        })
+    }
+}
+
+// a list is only ever a whole value, like the solutions of solve, so only there list(...) is one
+fn stored_value(expr: Expr) -> Expr {
+    match expr {
+        Expr::Call(name, items) if name == LIST => Expr::List(items),
+        expr => expr,
+    }
+}
+
+fn invalid(what: String) -> CalcError {
+    CalcError::FileError(format!("Not a valid session file, {}", what))
+}
+
+fn expression(name: &str, text: &str) -> Result<Expr, CalcError> {
+    if !is_valid_name(name) {
+        return Err(invalid(format!("{} is not a valid name", name)));
+    }
+    parse(text).map_err(|d| invalid(format!("{}: {}", name, d.error)))
+}
+
+fn sources(exprs: &HashMap<String, Expr>) -> Result<BTreeMap<String, String>, CalcError> {
+    exprs.iter().map(|(name, expr)| Ok((name.clone(), source(name, expr)?))).collect()
+}
+
+// text that parses back to the same expression, floats would come back as exact decimals so
+// they are written as float(...), and lists, which have no syntax, as list(...)
+fn source(name: &str, expr: &Expr) -> Result<String, CalcError> {
+    Ok(mark_floats(name, expr)?.to_string())
+}
+
+fn mark_floats(name: &str, expr: &Expr) -> Result<Expr, CalcError> {
+    let all = |items: &[Expr]| items.iter().map(|e| mark_floats(name, e)).collect::<Result<Vec<_>, _>>();
+    let boxed = |e: &Expr| mark_floats(name, e).map(Box::new);
+    Ok(match expr {
+        Expr::Number(Number::Float(x)) => {
+            // Display gives the shortest digits that read back as the same f64, they go in as a
+            // name because a Number would show them as a fraction. Infinity and NaN have no digits.
+            if !x.is_finite() {
+                return Err(CalcError::FileError(format!("{} contains {}, which cannot be saved", name, x)));
+            }
+            let float = Expr::Call("float".into(), vec![Expr::Var(x.abs().to_string())]);
+            if x.is_sign_negative() { Expr::Neg(Box::new(float)) } else { float }
+        }
+        Expr::Number(_) | Expr::Var(_) => expr.clone(),
+        Expr::Add(items) => Expr::Add(all(items)?),
+        Expr::Mul(items) => Expr::Mul(all(items)?),
+        Expr::List(items) => Expr::Call(LIST.into(), all(items)?),
+        Expr::Call(function, args) => Expr::Call(function.clone(), all(args)?),
+        Expr::Sub(a, b) => Expr::Sub(boxed(a)?, boxed(b)?),
+        Expr::Div(a, b) => Expr::Div(boxed(a)?, boxed(b)?),
+        Expr::Pow(a, b) => Expr::Pow(boxed(a)?, boxed(b)?),
+        Expr::Equation(a, b) => Expr::Equation(boxed(a)?, boxed(b)?),
+        Expr::Inequality(a, relation, b) => Expr::Inequality(boxed(a)?, *relation, boxed(b)?),
+        Expr::Neg(a) => Expr::Neg(boxed(a)?),
+    })
+}
@@ -0,0 +1,328 @@
+use crate::engine::Expr;
+use crate::engine::Number;
+use std::cmp::Ordering;
+
+// Simplification works in two steps. `canonical` rewrites the tree into a form without Sub, Neg
+// and Div (a - b is a + -1*b, a / b is a * b^-1) where every Add is a sorted list of
+// coefficient * term with like terms collected, and every Mul is a coefficient followed by
+// sorted base^exponent factors with equal bases merged. `present` then turns that back into
+// something readable, with Neg for negative coefficients and Div for negative exponents.
+pub fn normalize(expr: Expr) -> Expr {
+    present(canonical(expr))
+}
+
+fn canonical(expr: Expr) -> Expr {
+    match expr {
+        Expr::Number(_) | Expr::Var(_) => expr,
+        Expr::Neg(a) => canonical_mul(vec![Expr::Number(-Number::one()), canonical(*a)]),
+        Expr::Sub(a, b) => {
+            let negated = canonical_mul(vec![Expr::Number(-Number::one()), canonical(*b)]);
+            canonical_add(vec![canonical(*a), negated])
+        }
+        Expr::Add(terms) => canonical_add(terms.into_iter().map(canonical).collect()),
+        Expr::Mul(factors) => canonical_mul(factors.into_iter().map(canonical).collect()),
+        Expr::Div(a, b) => {
+            let inverse = canonical_pow(canonical(*b), Expr::Number(-Number::one()));
+            canonical_mul(vec![canonical(*a), inverse])
+        }
+        Expr::Pow(a, b) => canonical_pow(canonical(*a), canonical(*b)),
+        Expr::Call(name, args) => Expr::Call(name, args.into_iter().map(canonical).collect()),
+        Expr::Equation(a, b) => Expr::Equation(Box::new(canonical(*a)), Box::new(canonical(*b))),
+        Expr::Inequality(a, relation, b) => Expr::Inequality(Box::new(canonical(*a)), relation, Box::new(canonical(*b))),
+        Expr::List(items) => Expr::List(items.into_iter().map(canonical).collect()),
+    }
+}
+
+// splits a canonical term into its numeric coefficient and the rest, 3*x*y -> (3, x*y)
+fn split_coefficient(term: Expr) -> (Number, Option<Expr>) {
+    match term {
+        Expr::Number(n) => (n, None),
+        Expr::Mul(mut factors) => match factors.first() {
+            Some(Expr::Number(n)) => {
+                let n = n.clone();
+                factors.remove(0);
+                if factors.len() == 1 {
+                    (n, factors.pop())
+                } else {
+                    (n, Some(Expr::Mul(factors)))
+                }
+            }
+            _ => (Number::one(), Some(Expr::Mul(factors))),
+        },
+        other => (Number::one(), Some(other)),
+    }
+}
+
+fn with_coefficient(coefficient: Number, term: Expr) -> Expr {
+    if coefficient.is_one() {
+        return term;
+    }
+    match term {
+        Expr::Mul(mut factors) => {
+            factors.insert(0, Expr::Number(coefficient));
+            Expr::Mul(factors)
+        }
+        other => Expr::Mul(vec![Expr::Number(coefficient), other]),
+    }
+}
+
+fn canonical_add(terms: Vec<Expr>) -> Expr {
+    let mut constant = Number::zero();
+    let mut collected: Vec<(Expr, Number)> = Vec::new();
+
+    let mut pending = terms;
+    while let Some(term) = pending.pop() {
+        if let Expr::Add(inner) = term {
+            pending.extend(inner);
+            continue;
+        }
+        match split_coefficient(term) {
+            (n, None) => constant = constant + n,
+            (n, Some(rest)) => match collected.iter_mut().find(|(existing, _)| *existing == rest) {
+                Some((_, coefficient)) => *coefficient = coefficient.clone() + n,
+                None => collected.push((rest, n)),
+            },
+        }
+    }
+
+    let mut result: Vec<Expr> = collected
+        .into_iter()
+        .filter(|(_, coefficient)| !coefficient.is_zero())
+        .map(|(term, coefficient)| with_coefficient(coefficient, term))
+        .collect();
+    result.sort_by(compare_terms);
+
+    if !constant.is_zero() || (result.is_empty() && !constant.is_exact()) {
+        result.push(Expr::Number(constant));
+    }
+
+    match result.len() {
+        0 => Expr::Number(Number::zero()),
+        1 => result.pop().unwrap(),
+        _ => Expr::Add(result),
+    }
+}
+
+// splits a canonical factor into base and exponent, x^2 -> (x, 2), y -> (y, 1)
+fn split_power(factor: Expr) -> (Expr, Expr) {
+    match factor {
+        Expr::Pow(base, exp) => (*base, *exp),
+        other => (other, Expr::Number(Number::one())),
+    }
+}
+
+fn canonical_mul(factors: Vec<Expr>) -> Expr {
+    let mut coefficient = Number::one();
+    let mut collected: Vec<(Expr, Vec<Expr>)> = Vec::new();
+
+    let mut pending = factors;
+    while let Some(factor) = pending.pop() {
+        match factor {
+            Expr::Mul(inner) => pending.extend(inner),
+            Expr::Number(n) => coefficient = coefficient * n,
+            other => {
+                let (base, exp) = split_power(other);
+                match collected.iter_mut().find(|(existing, _)| *existing == base) {
+                    Some((_, exponents)) => exponents.push(exp),
+                    None => collected.push((base, vec![exp])),
+                }
+            }
+        }
+    }
+
+    if coefficient.is_zero() {
+        return Expr::Number(coefficient);
+    }
+
+    let mut result: Vec<Expr> = Vec::new();
+    for (base, exponents) in collected {
+        let exponent = if exponents.len() == 1 {
+            exponents.into_iter().next().unwrap()
+        } else {
+            canonical_add(exponents)
+        };
+        match canonical_pow(base, exponent) {
+            Expr::Number(n) => coefficient = coefficient * n,
+            Expr::Mul(inner) => {
+                for factor in inner {
+                    match factor {
+                        Expr::Number(n) => coefficient = coefficient * n,
+                        other => result.push(other),
+                    }
+                }
+            }
+            other => result.push(other),
+        }
+    }
+    result.sort_by(compare_factors);
+
+    if coefficient.is_zero() {
+        return Expr::Number(coefficient);
+    }
+    if !coefficient.is_one() || (result.is_empty() && !coefficient.is_exact()) {
+        result.insert(0, Expr::Number(coefficient));
+    }
+
+    match result.len() {
+        0 => Expr::Number(Number::one()),
+        1 => result.pop().unwrap(),
+        _ => Expr::Mul(result),
+    }
+}
+
+fn canonical_pow(base: Expr, exp: Expr) -> Expr {
+    if let Expr::Number(e) = &exp {
+        if e.is_zero() {
+            return Expr::Number(Number::one());
+        }
+        if e.is_one() {
+            return base;
+        }
+    }
+
+    let integer_exponent = matches!(&exp, Expr::Number(e) if e.is_exact() && e.to_integer().is_some());
+    match (base, exp) {
+        (Expr::Number(b), Expr::Number(e)) => match b.pow(&e) {
+            Ok(n) => Expr::Number(n),
+            Err(_) => Expr::Pow(Box::new(Expr::Number(b)), Box::new(Expr::Number(e))),
+        },
+        (Expr::Number(b), _) if b.is_one() => Expr::Number(b),
+        // (x^a)^n = x^(a*n) only holds for whole n, (x^2)^(1/2) is |x|
+        (Expr::Pow(inner, a), exp) if integer_exponent => {
+            let merged = canonical_mul(vec![*a, exp]);
+            canonical_pow(*inner, merged)
+        }
+        (Expr::Mul(factors), exp) if integer_exponent => {
+            canonical_mul(factors.into_iter().map(|f| canonical_pow(f, exp.clone())).collect())
+        }
+        (base, exp) => Expr::Pow(Box::new(base), Box::new(exp)),
+    }
+}
+
+// total order used for sorting, numbers first, then names alphabetically
+fn rank(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Number(_) => 0,
+        Expr::Var(_) => 1,
+        Expr::Call(_, _) => 2,
+        Expr::Pow(_, _) => 3,
+        _ => 4,
+    }
+}
+
+fn compare_exprs(a: &Expr, b: &Expr) -> Ordering {
+    match (a, b) {
+        (Expr::Var(x), Expr::Var(y)) => x.cmp(y),
+        (Expr::Call(f, x), Expr::Call(g, y)) => f.cmp(g).then_with(|| format!("{:?}", x).cmp(&format!("{:?}", y))),
+        _ => rank(a).cmp(&rank(b)).then_with(|| format!("{:?}", a).cmp(&format!("{:?}", b))),
+    }
+}
+
+// factors are ordered by their base, so x * y^2 * z reads alphabetically
+fn compare_factors(a: &Expr, b: &Expr) -> Ordering {
+    let (base_a, exp_a) = split_power(a.clone());
+    let (base_b, exp_b) = split_power(b.clone());
+    compare_exprs(&base_a, &base_b).then_with(|| compare_exprs(&exp_a, &exp_b))
+}
+
+// numeric degree of a term, used to list polynomials from the highest power down
+fn degree(term: &Expr) -> f64 {
+    match term {
+        Expr::Number(_) => 0.0,
+        Expr::Mul(factors) => factors.iter().map(degree).sum(),
+        Expr::Pow(_, exp) => match &**exp {
+            Expr::Number(n) => n.to_f64(),
+            _ => 1.0,
+        },
+        _ => 1.0,
+    }
+}
+
+fn compare_terms(a: &Expr, b: &Expr) -> Ordering {
+    let (_, rest_a) = split_coefficient(a.clone());
+    let (_, rest_b) = split_coefficient(b.clone());
+    degree(b)
+        .partial_cmp(&degree(a))
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| match (&rest_a, &rest_b) {
+            (Some(x), Some(y)) => compare_factors(x, y),
+            _ => Ordering::Equal,
+        })
+}
+
+fn product(mut factors: Vec<Expr>) -> Expr {
+    match factors.len() {
+        0 => Expr::Number(Number::one()),
+        1 => factors.pop().unwrap(),
+        _ => Expr::Mul(factors),
+    }
+}
+
+fn present(expr: Expr) -> Expr {
+    match expr {
+        Expr::Add(terms) => Expr::Add(terms.into_iter().map(present).collect()),
+        Expr::Mul(factors) => present_mul(factors),
+        Expr::Pow(base, exp) => match *exp {
+            Expr::Number(e) if e < Number::zero() => Expr::Div(
+                Box::new(Expr::Number(Number::one())),
+                Box::new(present(canonical_pow(*base, Expr::Number(-e)))),
+            ),
+            exp => Expr::Pow(Box::new(present(*base)), Box::new(present(exp))),
+        },
+        Expr::Call(name, args) => Expr::Call(name, args.into_iter().map(present).collect()),
+        Expr::Equation(a, b) => Expr::Equation(Box::new(present(*a)), Box::new(present(*b))),
+        Expr::Inequality(a, relation, b) => Expr::Inequality(Box::new(present(*a)), relation, Box::new(present(*b))),
+        Expr::List(items) => Expr::List(items.into_iter().map(present).collect()),
+        other => other,
+    }
+}
+
+// coefficient * factors, negative exponents and the coefficient's denominator go below the line
+fn present_mul(factors: Vec<Expr>) -> Expr {
+    let mut coefficient = Number::one();
+    let mut numerator: Vec<Expr> = Vec::new();
+    let mut denominator: Vec<Expr> = Vec::new();
+
+    for factor in factors {
+        match factor {
+            Expr::Number(n) => coefficient = n,
+            Expr::Pow(base, exp) => match *exp {
+                Expr::Number(e) if e < Number::zero() => denominator.push(present(canonical_pow(*base, Expr::Number(-e)))),
+                exp => numerator.push(present(Expr::Pow(base, Box::new(exp)))),
+            },
+            other => numerator.push(present(other)),
+        }
+    }
+
+    let negative = coefficient < Number::zero();
+    if negative {
+        coefficient = -coefficient;
+    }
+    match coefficient.to_rational() {
+        Some(r) if coefficient.is_exact() && !r.is_integer() => {
+            numerator.insert(0, Expr::Number(Number::from(r.numer().clone())));
+            denominator.insert(0, Expr::Number(Number::from(r.denom().clone())));
+        }
+        _ => {
+            if !coefficient.is_one() || numerator.is_empty() {
+                numerator.insert(0, Expr::Number(coefficient));
+            }
+        }
+    }
+    // 1/2 * x reads as x/2 rather than 1*x/2
+    if numerator.len() > 1 && matches!(&numerator[0], Expr::Number(n) if n.is_one()) {
+        numerator.remove(0);
+    }
+
+    let result = if denominator.is_empty() {
+        product(numerator)
+    } else {
+        Expr::Div(Box::new(product(numerator)), Box::new(product(denominator)))
+    };
+
+    if negative {
+        Expr::Neg(Box::new(result))
+    } else {
+        result
+    }
+}
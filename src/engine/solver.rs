@@ -0,0 +1,252 @@
+use crate::engine::CalcError;
+use crate::engine::Expr;
+use crate::engine::Number;
+use crate::engine::derivative::{depends_on, differentiate};
+use crate::engine::evaluator::eval_ast;
+use crate::engine::simplify::normalize;
+use num_traits::ToPrimitive;
+use std::collections::{HashMap, HashSet};
+
+// highest power that gets expanded while pulling polynomial coefficients out of a tree
+const MAX_EXPANDED_DEGREE: i64 = 8;
+// default search range and resolution of the numeric fallback
+const DEFAULT_BRACKET: (f64, f64) = (-100.0, 100.0);
+const SAMPLES: usize = 2000;
+const TOLERANCE: f64 = 1e-12;
+
+fn num(n: i64) -> Expr {
+    Expr::Number(Number::from(n))
+}
+
+fn is_zero(expr: &Expr) -> bool {
+    matches!(expr, Expr::Number(n) if n.is_zero())
+}
+
+// solutions of expr == 0 for var, expr has to be fully expanded already (no other unknown
+// besides the ones the caller wants to keep symbolic). bracket is where the numeric fallback
+// searches, keeping only the solutions inside of it is up to the caller.
+pub fn solve(expr: &Expr, var: &str, bracket: Option<(f64, f64)>) -> Result<Vec<Expr>, CalcError> {
+    let expr = match normalize(expr.clone()) {
+        Expr::Neg(inner) => *inner,
+        other => other,
+    };
+    // a fraction is zero where its numerator is, as long as the denominator is not
+    let (numerator, denominator) = match &expr {
+        Expr::Div(p, q) => ((**p).clone(), Some((**q).clone())),
+        other => (other.clone(), None),
+    };
+
+    let mut solutions = match polynomial_coefficients(&numerator, var) {
+        Some(coefficients) if coefficients.len() <= 3 => solve_polynomial(&coefficients, var)?,
+        _ => solve_numeric(&numerator, var, bracket.unwrap_or(DEFAULT_BRACKET))?,
+    };
+
+    if let Some(denominator) = denominator {
+        solutions.retain(|s| match substitute_number(&denominator, var, s) {
+            Some(value) => !value.is_zero(),
+            None => true,
+        });
+    }
+    Ok(solutions)
+}
+
+// coefficients [c0, c1, c2, ...] of expr as a polynomial in var, None if it is not one
+pub fn polynomial_coefficients(expr: &Expr, var: &str) -> Option<Vec<Expr>> {
+    let mut coefficients = raw_coefficients(expr, var)?;
+    coefficients = coefficients.into_iter().map(normalize).collect();
+    while coefficients.len() > 1 && is_zero(coefficients.last().unwrap()) {
+        coefficients.pop();
+    }
+    Some(coefficients)
+}
+
+fn raw_coefficients(expr: &Expr, var: &str) -> Option<Vec<Expr>> {
+    if !depends_on(expr, var) {
+        return Some(vec![expr.clone()]);
+    }
+    match expr {
+        Expr::Var(_) => Some(vec![num(0), num(1)]),
+        Expr::Add(terms) => {
+            let mut sum: Vec<Expr> = vec![num(0)];
+            for term in terms {
+                sum = add_coefficients(&sum, &raw_coefficients(term, var)?);
+            }
+            Some(sum)
+        }
+        Expr::Sub(a, b) => {
+            let negated: Vec<Expr> = raw_coefficients(b, var)?.into_iter().map(|c| Expr::Neg(Box::new(c))).collect();
+            Some(add_coefficients(&raw_coefficients(a, var)?, &negated))
+        }
+        Expr::Neg(a) => Some(raw_coefficients(a, var)?.into_iter().map(|c| Expr::Neg(Box::new(c))).collect()),
+        Expr::Mul(factors) => {
+            let mut product: Vec<Expr> = vec![num(1)];
+            for factor in factors {
+                product = mul_coefficients(&product, &raw_coefficients(factor, var)?);
+            }
+            Some(product)
+        }
+        Expr::Div(a, b) if !depends_on(b, var) => Some(
+            raw_coefficients(a, var)?
+                .into_iter()
+                .map(|c| Expr::Div(Box::new(c), b.clone()))
+                .collect(),
+        ),
+        Expr::Pow(base, exp) => {
+            let power = match &**exp {
+                Expr::Number(n) if n.is_exact() => n.to_integer()?,
+                _ => return None,
+            };
+            let power = power.to_i64().filter(|p| (0..=MAX_EXPANDED_DEGREE).contains(p))?;
+            let base = raw_coefficients(base, var)?;
+            let mut result: Vec<Expr> = vec![num(1)];
+            for _ in 0..power {
+                result = mul_coefficients(&result, &base);
+            }
+            Some(result)
+        }
+        _ => None,
+    }
+}
+
+fn add_coefficients(a: &[Expr], b: &[Expr]) -> Vec<Expr> {
+    (0..a.len().max(b.len()))
+        .map(|i| match (a.get(i), b.get(i)) {
+            (Some(x), Some(y)) => normalize(Expr::Add(vec![x.clone(), y.clone()])),
+            (Some(x), None) | (None, Some(x)) => x.clone(),
+            (None, None) => num(0),
+        })
+        .collect()
+}
+
+fn mul_coefficients(a: &[Expr], b: &[Expr]) -> Vec<Expr> {
+    let mut result: Vec<Expr> = vec![num(0); a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            let term = Expr::Mul(vec![x.clone(), y.clone()]);
+            result[i + j] = normalize(Expr::Add(vec![result[i + j].clone(), term]));
+        }
+    }
+    result
+}
+
+// c0 + c1*x (+ c2*x^2) == 0, exact closed forms with the coefficients left symbolic if they are
+fn solve_polynomial(coefficients: &[Expr], var: &str) -> Result<Vec<Expr>, CalcError> {
+    match coefficients {
+        [c] if is_zero(c) => Err(CalcError::InvalidExpression(format!("Equation holds for every {}", var))),
+        [_] => Ok(Vec::new()),
+        [c0, c1] => Ok(vec![normalize(Expr::Div(
+            Box::new(Expr::Neg(Box::new(c0.clone()))),
+            Box::new(c1.clone()),
+        ))]),
+        [c, b, a] => {
+            let discriminant = normalize(Expr::Sub(
+                Box::new(Expr::Pow(Box::new(b.clone()), Box::new(num(2)))),
+                Box::new(Expr::Mul(vec![num(4), a.clone(), c.clone()])),
+            ));
+            let root = |sign: i64| {
+                normalize(Expr::Div(
+                    Box::new(Expr::Add(vec![
+                        Expr::Neg(Box::new(b.clone())),
+                        Expr::Mul(vec![num(sign), Expr::Call("sqrt".into(), vec![discriminant.clone()])]),
+                    ])),
+                    Box::new(Expr::Mul(vec![num(2), a.clone()])),
+                ))
+            };
+            match &discriminant {
+                Expr::Number(d) if *d < Number::zero() => Ok(Vec::new()),
+                Expr::Number(d) if d.is_zero() => Ok(vec![root(1)]),
+                _ => Ok(vec![root(-1), root(1)]),
+            }
+        }
+        _ => Err(CalcError::HowDidWeGetHere("solve_polynomial called with a higher degree".into())),
+    }
+}
+
+fn substitute_number(expr: &Expr, var: &str, value: &Expr) -> Option<Number> {
+    let mut vars = HashMap::new();
+    vars.insert(var.to_string(), value.clone());
+    match eval_ast(expr, &vars, &HashMap::new(), &mut HashSet::new()) {
+        Ok(Expr::Number(n)) => Some(n),
+        _ => None,
+    }
+}
+
+fn evaluate_at(expr: &Expr, var: &str, x: f64) -> Option<f64> {
+    substitute_number(expr, var, &Expr::Number(Number::Float(x)))
+        .map(|n| n.to_f64())
+        .filter(|f| f.is_finite())
+}
+
+// scans the bracket for sign changes, narrows each one down by bisection and polishes the
+// result with a few Newton steps on the symbolic derivative
+fn solve_numeric(expr: &Expr, var: &str, (lo, hi): (f64, f64)) -> Result<Vec<Expr>, CalcError> {
+    if evaluate_at(expr, var, (lo + hi) / 2.0).is_none() && evaluate_at(expr, var, lo).is_none() {
+        return Err(CalcError::InvalidExpression(format!("Cannot solve numerically, the equation depends on more than {}", var)));
+    }
+    let derivative = differentiate(expr, var).ok();
+
+    let mut roots: Vec<f64> = Vec::new();
+    let step = (hi - lo) / SAMPLES as f64;
+    let mut previous: Option<(f64, f64)> = None;
+    for i in 0..=SAMPLES {
+        let x = lo + step * i as f64;
+        let Some(fx) = evaluate_at(expr, var, x) else {
+            previous = None;
+            continue;
+        };
+        if fx == 0.0 {
+            roots.push(x);
+        } else if let Some((px, pfx)) = previous
+            && pfx != 0.0
+            && pfx.signum() != fx.signum()
+        {
+            let root = bisect(expr, var, px, x, pfx);
+            let root = polish(expr, derivative.as_ref(), var, root, px, x);
+            // a sign change across a pole is not a root
+            let scale = pfx.abs().max(fx.abs()).max(1.0);
+            if evaluate_at(expr, var, root).is_some_and(|f| f.abs() <= 1e-6 * scale) {
+                roots.push(root);
+            }
+        }
+        previous = Some((x, fx));
+    }
+
+    roots.dedup_by(|a, b| (*a - *b).abs() <= 1e-9 * a.abs().max(1.0));
+    Ok(roots.into_iter().map(|r| Expr::Number(Number::Float(r))).collect())
+}
+
+fn bisect(expr: &Expr, var: &str, mut a: f64, mut b: f64, mut fa: f64) -> f64 {
+    while (b - a).abs() > TOLERANCE * a.abs().max(1.0) {
+        let mid = (a + b) / 2.0;
+        match evaluate_at(expr, var, mid) {
+            Some(0.0) => return mid,
+            Some(fm) if fm.signum() == fa.signum() => {
+                a = mid;
+                fa = fm;
+            }
+            Some(_) => b = mid,
+            None => break,
+        }
+    }
+    (a + b) / 2.0
+}
+
+fn polish(expr: &Expr, derivative: Option<&Expr>, var: &str, mut x: f64, lo: f64, hi: f64) -> f64 {
+    let Some(derivative) = derivative else {
+        return x;
+    };
+    for _ in 0..5 {
+        let (Some(fx), Some(dfx)) = (evaluate_at(expr, var, x), evaluate_at(derivative, var, x)) else {
+            break;
+        };
+        if dfx == 0.0 {
+            break;
+        }
+        let next = x - fx / dfx;
+        if !(lo..=hi).contains(&next) {
+            break;
+        }
+        x = next;
+    }
+    x
+}
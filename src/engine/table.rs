@@ -0,0 +1,131 @@
+use crate::engine::CalcError;
+use crate::engine::Number;
+use crate::engine::NumberFormat;
+use crate::engine::Value;
+use std::cmp::Ordering;
+
+// how many values a table may have, so a tiny step cannot keep the calculator busy forever
+pub const MAX_CELLS: usize = 100_000;
+
+// the values one variable of a table runs through, from start to end in steps of step. With exact
+// numbers 0.1 steps stay exact instead of drifting like floats do.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableRange {
+    pub var: String,
+    pub start: Number,
+    pub end: Number,
+    pub step: Number,
+}
+
+impl TableRange {
+    pub fn new(var: &str, start: Number, end: Number, step: Number) -> Self {
+        Self { var: var.to_string(), start, end, step }
+    }
+
+    pub(crate) fn values(&self) -> Result<Vec<Number>, CalcError> {
+        let zero = Number::zero();
+        if self.step == zero {
+            return Err(CalcError::InvalidExpression(format!("The step of {} cannot be 0", self.var)));
+        }
+        let ascending = self.step > zero;
+        if (ascending && self.end < self.start) || (!ascending && self.end > self.start) {
+            return Err(CalcError::InvalidExpression(format!(
+                "Going from {} in steps of {} never reaches {}", self.start, self.step, self.end
+            )));
+        }
+        let mut values = Vec::new();
+        let mut value = self.start.clone();
+        while if ascending { value <= self.end } else { value >= self.end } {
+            if values.len() == MAX_CELLS {
+                return Err(CalcError::InvalidExpression(format!("A table can have at most {} values", MAX_CELLS)));
+            }
+            values.push(value.clone());
+            value = value + self.step.clone();
+        }
+        Ok(values)
+    }
+}
+
+// Values of an expression over one variable, one row per value, or over two, one row per value of
+// the first and one column per value of the second. The first column holds the values of the
+// first variable, a cell where the expression failed keeps the error.
+#[derive(Debug)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<Result<Value, CalcError>>>,
+}
+
+impl Table {
+    // numbers in order, then everything else, then errors. Sorting is stable so rows with equal
+    // values keep their order.
+    pub fn sort_by_column(&mut self, column: usize, descending: bool) {
+        let rank = |cell: Option<&Result<Value, CalcError>>| match cell {
+            Some(Ok(Value::Number(_))) => 0,
+            Some(Ok(_)) => 1,
+            _ => 2,
+        };
+        self.rows.sort_by(|a, b| {
+            let (a, b) = (a.get(column), b.get(column));
+            let order = match (a, b) {
+                (Some(Ok(Value::Number(x))), Some(Ok(Value::Number(y)))) => x.partial_cmp(y).unwrap_or(Ordering::Equal),
+                _ => rank(a).cmp(&rank(b)),
+            };
+            if descending && rank(a) == 0 && rank(b) == 0 { order.reverse() } else { order }
+        });
+    }
+
+    // the table as aligned columns under a header line
+    pub fn display_with(&self, format: NumberFormat) -> String {
+        let mut lines: Vec<Vec<String>> = vec![self.headers.clone()];
+        lines.extend(self.rows.iter().map(|row| row.iter().map(|cell| cell_text(cell, format)).collect()));
+        let widths: Vec<usize> = (0..self.headers.len())
+            .map(|column| lines.iter().filter_map(|line| line.get(column)).map(|text| text.chars().count()).max().unwrap_or(0))
+            .collect();
+        let rule = widths.iter().map(|width| "-".repeat(*width)).collect::<Vec<_>>().join("-+-");
+        let mut text: Vec<String> = lines
+            .iter()
+            .map(|line| {
+                let cells: Vec<String> = line.iter().zip(&widths).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+                cells.join(" | ").trim_end().to_string()
+            })
+            .collect();
+        text.insert(1, rule);
+        text.join("\n")
+    }
+
+    // comma separated with a header line. Failed cells stay empty, a spreadsheet reads that as a
+    // missing value while an error message would turn the whole column into text. Numbers are
+    // always decimals, a spreadsheet would take a fraction like 1/2 for a date.
+    pub fn to_csv(&self) -> String {
+        let format = NumberFormat { fractions: false, precision: None };
+        let mut csv = String::new();
+        let mut line = |cells: Vec<String>| {
+            csv.push_str(&cells.iter().map(|cell| csv_field(cell)).collect::<Vec<_>>().join(","));
+            csv.push('\n');
+        };
+        line(self.headers.clone());
+        for row in &self.rows {
+            line(row.iter().map(|cell| match cell {
+                Ok(value) => value.display_with(format).to_string(),
+                Err(_) => String::new(),
+            }).collect());
+        }
+        csv
+    }
+}
+
+fn cell_text(cell: &Result<Value, CalcError>, format: NumberFormat) -> String {
+    match cell {
+        Ok(value) => value.display_with(format).to_string(),
+        Err(err) => err.to_string(),
+    }
+}
+
+// quoted when it contains a separator, a quote or a line break, quotes inside are doubled
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
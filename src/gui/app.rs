@@ -1,45 +1,186 @@
-use crate::engine::CalculatorEngine;
+use crate::autosave;
+use crate::gui::plot::PlotPanel;
+use crate::gui::table::TablePanel;
+use calculator::{CalculatorEngine, Diagnostic, Expr, NumberFormat, Value};
 use eframe::egui;
 
 pub struct CalculatorApp {
     engine: CalculatorEngine,
     input: String,
-    last_result: Option<String>,
+    last_result: Option<Result<Vec<Value>, Diagnostic>>, // kept unformatted so a format change shows right away
+    last_input: String, // what last_result was computed from, errors are underlined in it
+    session_path: String, // file the Save and Load buttons use
+    session_status: Option<String>, // outcome of the last save, load or restore
+    editing: Option<VariableEdit>, // the variable whose definition is being edited in the variables panel
+    plot: PlotPanel,
+    table: TablePanel,
+    table_open: bool, // whether the table window is shown
+}
+
+// a definition opened with a double click, it is submitted as "name = text" or "name := text"
+struct VariableEdit {
+    name: String,
+    live: bool,
+    text: String,
 }
 
 impl CalculatorApp {
     pub fn new() -> Self {
+        let mut engine = CalculatorEngine::new();
+        let session_status = match autosave::restore(&mut engine) {
+            Ok(true) => Some("Restored the last session".to_string()),
+            Ok(false) => None,
+            Err(err) => Some(format!("Could not restore the last session: {}", err)),
+        };
         Self {
-            engine: CalculatorEngine::new(),
+            engine,
             input: String::new(),
             last_result: None,
+            last_input: String::new(),
+            session_path: "session.json".to_string(),
+            session_status,
+            editing: None,
+            plot: PlotPanel::new(),
+            table: TablePanel::new(),
+            table_open: false,
         }
     }
 
     pub fn on_submit(&mut self) {
-        match self.engine.evaluate(&self.input) {
-            Ok(result) => {
-                let s = format!("{:?}", result);
-                self.last_result = Some(s);
+        let input = self.input.clone();
+        self.evaluate(input);
+    }
+
+    // evaluates input and shows its result, without touching what is being typed
+    fn evaluate(&mut self, input: String) {
+        let result = self.engine.evaluate_with_diagnostics(&input);
+        if result.is_ok() {
+            self.autosave();
+        }
+        self.last_result = Some(result);
+        self.last_input = input;
+    }
+
+    // the list of variables with their values, symbolic ones are highlighted since they are usually
+    // the reason a result is not a number
+    fn variables_panel(&mut self, ui: &mut egui::Ui) {
+        let format = self.engine.number_format();
+        let variables = self.engine.variables();
+        if variables.is_empty() {
+            ui.label("No variables yet");
+            return;
+        }
+        let mut submit = None;
+        let mut cancel = false;
+        let mut remove = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (name, stored) in variables {
+                // the stored value can still mention variables that were only defined later,
+                // evaluating the name fills them in like typing it would
+                let value = self.engine.eval(&Expr::Var(name.clone())).unwrap_or_else(|_| stored.clone());
+                let binding = self.engine.binding(&name).cloned();
+                if let Some(edit) = self.editing.as_mut().filter(|edit| edit.name == name) {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} {}", name, if edit.live { ":=" } else { "=" }));
+                        let response = ui.text_edit_singleline(&mut edit.text);
+                        // Enter submits, Escape or clicking elsewhere leaves the variable as it was
+                        if response.lost_focus() {
+                            if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                submit = Some(format!("{} {} {}", name, if edit.live { ":=" } else { "=" }, edit.text));
+                            } else {
+                                cancel = true;
+                            }
+                        } else if !response.has_focus() {
+                            response.request_focus();
+                        }
+                    });
+                    continue;
+                }
+
+                let shown = match &binding {
+                    Some(expr) => format!("{} := {} = {}", name, expr.display_with(format), value.display_with(format)),
+                    None => format!("{} = {}", name, value.display_with(format)),
+                };
+                let mut text = egui::RichText::new(shown);
+                let mut hover = "Double-click to edit".to_string();
+                if let Value::Expression(expr) = &value
+                    && !expr.variables().is_empty()
+                {
+                    let unknown = expr.variables().into_iter().collect::<Vec<_>>();
+                    text = text.color(ui.visuals().warn_fg_color);
+                    hover = format!("Still symbolic, {} {} not defined. {}", unknown.join(", "), if unknown.len() == 1 { "is" } else { "are" }, hover);
+                }
+                ui.horizontal(|ui| {
+                    if ui.small_button("x").on_hover_text(format!("Delete {}", name)).clicked() {
+                        remove = Some(name.clone());
+                    }
+                    let label = ui.add(egui::Label::new(text).sense(egui::Sense::click())).on_hover_text(hover);
+                    if label.double_clicked() {
+                        let live = binding.is_some();
+                        // exact text, the display format could round the value
+                        let text = match &binding {
+                            Some(expr) => expr.display_with(NumberFormat::default()).to_string(),
+                            None => stored.display_with(NumberFormat::default()).to_string(),
+                        };
+                        self.editing = Some(VariableEdit { name: name.clone(), live, text });
+                    }
+                });
             }
-            Err(err) => {
-                let s = format!("Error: {:?}", err);
-                self.last_result = Some(s);
+        });
+
+        if cancel {
+            self.editing = None;
+        }
+        // both go through evaluate so they show up in the history and can be undone
+        if let Some(input) = submit {
+            self.evaluate(input);
+            if matches!(self.last_result, Some(Ok(_))) {
+                self.editing = None;
             }
         }
+        if let Some(name) = remove {
+            self.evaluate(format!("unset {}", name));
+        }
+    }
+
+    fn autosave(&mut self) {
+        if let Err(err) = autosave::save(&self.engine) {
+            self.session_status = Some(format!("Could not save the session: {}", err));
+        }
+    }
+
+    fn save_session(&mut self) {
+        self.session_status = Some(match self.engine.save_session(&self.session_path) {
+            Ok(()) => format!("Saved session to {}", self.session_path),
+            Err(err) => err.to_string(),
+        });
+    }
+
+    fn load_session(&mut self) {
+        self.session_status = Some(match self.engine.load_session(&self.session_path) {
+            Ok(()) => {
+                self.last_result = None;
+                self.autosave();
+                format!("Loaded session from {}", self.session_path)
+            }
+            Err(err) => err.to_string(),
+        });
     }
 
+    #[allow(dead_code)]
     pub fn set_input(&mut self, input: String) {
         self.input = input;
     }
 
     pub fn run(self) {
         let options = eframe::NativeOptions::default();
-        eframe::run_native(
+        if let Err(err) = eframe::run_native(
             "Calculator",
             options,
             Box::new(move |_cc| Ok(Box::new(self) as Box<dyn eframe::App>)),
-        );
+        ) {
+            eprintln!("Error: {:?}", err);
+        }
     }
 
     fn append_char(&mut self, ch: char) {
@@ -87,14 +228,106 @@ impl CalculatorApp {
 }
 
 impl eframe::App for CalculatorApp {
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // taken before the text field sees them, so they undo calculations rather than typing
+        let undo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+        let redo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Y);
+        if ctx.input_mut(|i| i.consume_shortcut(&undo)) && self.engine.can_undo() {
+            self.evaluate("undo".to_string());
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&redo)) && self.engine.can_redo() {
+            self.evaluate("redo".to_string());
+        }
+
+        egui::SidePanel::right("history").show(ctx, |ui| {
+            ui.heading("History");
+            let format = self.engine.number_format();
+            let mut picked = None;
+            egui::ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
+                for (n, entry) in (1..).zip(self.engine.history()) {
+                    let text = format!("${}  {} = {}", n, entry.input, entry.value.display_with(format));
+                    if ui.selectable_label(false, text).on_hover_text("Click to edit again").clicked() {
+                        picked = Some(entry.input.clone());
+                    }
+                }
+            });
+            if let Some(input) = picked {
+                self.input = input;
+            }
+        });
+
+        egui::SidePanel::left("variables").show(ctx, |ui| {
+            egui::CollapsingHeader::new("Variables").default_open(true).show(ui, |ui| {
+                self.variables_panel(ui);
+            });
+        });
+
+        egui::Window::new("Table of values").open(&mut self.table_open).show(ctx, |ui| {
+            self.table.show(ui, &self.engine);
+        });
+
+        egui::TopBottomPanel::bottom("plot").resizable(true).default_height(320.0).show(ctx, |ui| {
+            self.plot.show(ui, &self.engine);
+        });
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.vertical(|ui| {
-                    if let Some(res) = &self.last_result {
-                        ui.label(format!("Result: {}", res));
-                    } else {
-                        ui.label("Result: -");
+                    match &self.last_result {
+                        Some(Ok(values)) => {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("Result: {}", self.engine.format_values(values)));
+                                if ui.small_button("Copy as LaTeX").clicked() {
+                                    ui.ctx().copy_text(self.engine.latex_values(values));
+                                }
+                                if ui.small_button("Copy as MathML").clicked() {
+                                    ui.ctx().copy_text(self.engine.mathml_values(values));
+                                }
+                            });
+                            let invalidated = self.engine.invalidated();
+                            if !invalidated.is_empty() {
+                                ui.label(format!("Updated: {}", invalidated.join(", ")));
+                            }
+                        }
+                        Some(Err(err)) => {
+                            ui.label("Error:");
+                            ui.label(egui::RichText::new(err.render(&self.last_input)).monospace());
+                        }
+                        None => {
+                            ui.label("Result: -");
+                        }
+                    }
+
+                    ui.horizontal(|ui| {
+                        let mut format = self.engine.number_format();
+                        ui.checkbox(&mut format.fractions, "Fractions");
+                        let mut fixed = format.precision.is_some();
+                        ui.checkbox(&mut fixed, "Fixed decimals");
+                        format.precision = if fixed {
+                            let mut digits = format.precision.unwrap_or(6);
+                            ui.add(egui::DragValue::new(&mut digits).range(0..=30));
+                            Some(digits)
+                        } else {
+                            None
+                        };
+                        if format != self.engine.number_format() {
+                            self.engine.set_number_format(format);
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Session file:");
+                        ui.text_edit_singleline(&mut self.session_path);
+                        if ui.button("Save").clicked() {
+                            self.save_session();
+                        }
+                        if ui.button("Load").clicked() {
+                            self.load_session();
+                        }
+                        ui.toggle_value(&mut self.table_open, "Table");
+                    });
+                    if let Some(status) = &self.session_status {
+                        ui.label(status);
                     }
 
                     ui.horizontal(|ui| {
@@ -102,6 +335,14 @@ impl eframe::App for CalculatorApp {
                         if ui.button("=").clicked() {
                             self.on_submit();
                         }
+                        let can_undo = self.engine.can_undo();
+                        if ui.add_enabled(can_undo, egui::Button::new("Undo")).on_hover_text(ctx.format_shortcut(&undo)).clicked() {
+                            self.evaluate("undo".to_string());
+                        }
+                        let can_redo = self.engine.can_redo();
+                        if ui.add_enabled(can_redo, egui::Button::new("Redo")).on_hover_text(ctx.format_shortcut(&redo)).clicked() {
+                            self.evaluate("redo".to_string());
+                        }
                     });
 
                     ui.separator();
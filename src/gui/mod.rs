@@ -1,3 +1,5 @@
 pub mod app;
+pub mod plot;
+pub mod table;
 
 pub use app::CalculatorApp;
\ No newline at end of file
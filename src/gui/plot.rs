@@ -0,0 +1,160 @@
+use calculator::{CalculatorEngine, Curve, Graph, Implicit, Relation};
+use eframe::egui;
+use egui_plot::{Legend, Line, LineStyle, Plot, Polygon};
+
+// one color per expression, all pieces of a graph share it so the legend stays readable
+const COLORS: [egui::Color32; 6] = [
+    egui::Color32::from_rgb(31, 119, 180),
+    egui::Color32::from_rgb(214, 39, 40),
+    egui::Color32::from_rgb(44, 160, 44),
+    egui::Color32::from_rgb(255, 127, 14),
+    egui::Color32::from_rgb(148, 103, 189),
+    egui::Color32::from_rgb(23, 190, 207),
+];
+
+// Graphs of expressions in x, entered like "sin(x); x^2/4". They are sampled again on every
+// frame over whatever range is visible, so zooming in shows detail instead of stretched lines.
+// Parametric and polar curves are traced over the parameter range set below them instead.
+// Equations and inequalities in x and y are found on a grid over the visible area, an
+// inequality is shaded where it holds and its border is dashed when the border is not part of it.
+pub struct PlotPanel {
+    input: String,
+    x_input: String, // x(t), several separated by ';' pair up with the ones in y_input
+    y_input: String,
+    r_input: String, // r(theta)
+    implicit_input: String, // equations and inequalities in x and y
+    from: f64,
+    to: f64,
+    samples: usize,
+}
+
+impl PlotPanel {
+    pub fn new() -> Self {
+        Self {
+            input: String::new(),
+            x_input: String::new(),
+            y_input: String::new(),
+            r_input: String::new(),
+            implicit_input: String::new(),
+            from: 0.0,
+            to: std::f64::consts::TAU,
+            samples: 500,
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, engine: &CalculatorEngine) {
+        let field = |ui: &mut egui::Ui, text: &mut String, hint: &str| {
+            ui.add(egui::TextEdit::singleline(text).hint_text(hint).desired_width(180.0));
+        };
+        ui.horizontal(|ui| {
+            ui.label("y(x) =");
+            field(ui, &mut self.input, "sin(x); x^2/4");
+            ui.label("x(t) =");
+            field(ui, &mut self.x_input, "cos(3*t)");
+            ui.label("y(t) =");
+            field(ui, &mut self.y_input, "sin(2*t)");
+            ui.label("r(θ) =");
+            field(ui, &mut self.r_input, "1 + cos(theta)");
+        });
+        ui.horizontal(|ui| {
+            ui.label("In x and y:");
+            field(ui, &mut self.implicit_input, "x^2 + y^2 == 25; y < 2*x + 1");
+        });
+        ui.horizontal(|ui| {
+            ui.label("t and θ from");
+            ui.add(egui::DragValue::new(&mut self.from).speed(0.1));
+            ui.label("to");
+            ui.add(egui::DragValue::new(&mut self.to).speed(0.1));
+            ui.label("samples");
+            ui.add(egui::DragValue::new(&mut self.samples).range(2..=100_000));
+        });
+
+        let mut errors = Vec::new();
+        let mut graphs: Vec<Graph> = Vec::new();
+        for input in expressions(&self.input) {
+            match engine.graph(input, "x") {
+                Ok(graph) => graphs.push(graph),
+                Err(err) => errors.push(format!("{}: {}", input.trim(), err)),
+            }
+        }
+        let mut curves: Vec<Curve> = Vec::new();
+        let (xs, ys) = (expressions(&self.x_input), expressions(&self.y_input));
+        if xs.len() != ys.len() {
+            errors.push("x(t) and y(t) need the same number of expressions".to_string());
+        }
+        for (x, y) in xs.into_iter().zip(ys) {
+            match engine.parametric(x, y, "t") {
+                Ok(curve) => curves.push(curve),
+                Err(err) => errors.push(format!("({}, {}): {}", x.trim(), y.trim(), err)),
+            }
+        }
+        for r in expressions(&self.r_input) {
+            match engine.polar(r, "theta") {
+                Ok(curve) => curves.push(curve),
+                Err(err) => errors.push(format!("r = {}: {}", r.trim(), err)),
+            }
+        }
+        let mut implicits: Vec<Implicit> = Vec::new();
+        for input in expressions(&self.implicit_input) {
+            match engine.implicit(input) {
+                Ok(implicit) => implicits.push(implicit),
+                Err(err) => errors.push(format!("{}: {}", input.trim(), err)),
+            }
+        }
+        for error in errors {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        let (from, to, samples) = (self.from, self.to, self.samples);
+        Plot::new("plot")
+            .legend(Legend::default())
+            .default_x_bounds(-10.0, 10.0)
+            .default_y_bounds(-10.0, 10.0)
+            .label_formatter(|name, point| match name {
+                "" => format!("x = {:.4}\ny = {:.4}", point.x, point.y),
+                name => format!("{}\nx = {:.4}\ny = {:.4}", name, point.x, point.y),
+            })
+            .show(ui, |plot_ui| {
+                let bounds = plot_ui.plot_bounds();
+                let width = (plot_ui.response().rect.width() as usize).max(100);
+                let sampled = graphs
+                    .iter()
+                    .map(|graph| (&graph.label, graph.sample(bounds.min()[0], bounds.max()[0], width)))
+                    .chain(curves.iter().map(|curve| (&curve.label, curve.sample(from, to, samples))));
+                let count = graphs.len() + curves.len();
+                for (n, (label, pieces)) in sampled.enumerate() {
+                    let color = COLORS[n % COLORS.len()];
+                    for (i, piece) in pieces.into_iter().enumerate() {
+                        plot_ui.line(Line::new(label.clone(), piece).id(egui::Id::new(("graph", n, i))).color(color));
+                    }
+                }
+
+                // cells of about 4 points, fine enough to look smooth without evaluating too often
+                let rect = plot_ui.response().rect;
+                let cells = [(rect.width() / 4.0) as usize, (rect.height() / 4.0) as usize];
+                for (n, implicit) in implicits.iter().enumerate() {
+                    let n = count + n;
+                    let color = COLORS[n % COLORS.len()];
+                    for (i, [low, high]) in implicit.region(bounds.min(), bounds.max(), cells).into_iter().enumerate() {
+                        let corners = vec![low, [high[0], low[1]], high, [low[0], high[1]]];
+                        let cell = Polygon::new(implicit.label.clone(), corners)
+                            .id(egui::Id::new(("region", n, i)))
+                            .fill_color(color.gamma_multiply(0.25))
+                            .stroke(egui::Stroke::new(0.0, color));
+                        plot_ui.polygon(cell);
+                    }
+                    let style = match implicit.relation {
+                        Some(Relation::Less | Relation::Greater) => LineStyle::dashed_loose(),
+                        _ => LineStyle::Solid,
+                    };
+                    for (i, line) in implicit.contour(bounds.min(), bounds.max(), cells).into_iter().enumerate() {
+                        plot_ui.line(Line::new(implicit.label.clone(), line).id(egui::Id::new(("contour", n, i))).color(color).style(style));
+                    }
+                }
+            });
+    }
+}
+
+fn expressions(input: &str) -> Vec<&str> {
+    input.split(';').filter(|s| !s.trim().is_empty()).collect()
+}
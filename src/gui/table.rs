@@ -0,0 +1,154 @@
+use calculator::{CalcError, CalculatorEngine, Number, Table, TableRange, Value};
+use eframe::egui;
+
+// the text fields of one variable of the table
+struct RangeInput {
+    var: String,
+    start: String,
+    end: String,
+    step: String,
+}
+
+impl RangeInput {
+    fn new(var: &str, start: &str, end: &str, step: &str) -> Self {
+        Self { var: var.to_string(), start: start.to_string(), end: end.to_string(), step: step.to_string() }
+    }
+
+    // start, end and step may be expressions using the session's variables
+    fn range(&self, engine: &CalculatorEngine) -> Result<TableRange, CalcError> {
+        let number = |text: &str| match engine.eval(&calculator::parse(text).map_err(|d| d.error)?)? {
+            Value::Number(n) => Ok(n),
+            other => Err(CalcError::InvalidExpression(format!("The range of {} needs numbers, {} is not one", self.var.trim(), other))),
+        };
+        let (start, end, step): (Number, Number, Number) = (number(&self.start)?, number(&self.end)?, number(&self.step)?);
+        Ok(TableRange::new(self.var.trim(), start, end, step))
+    }
+}
+
+// A table of values of an expression over one or two variables, the same as the table command
+// gives but sortable by clicking a column header and with export to CSV.
+pub struct TablePanel {
+    expr: String,
+    ranges: [RangeInput; 2],
+    two_variables: bool,
+    table: Option<Result<Table, CalcError>>,
+    sorted: Option<(usize, bool)>, // column and whether it is descending
+    csv_path: String,
+    status: Option<String>, // outcome of the last export
+}
+
+impl TablePanel {
+    pub fn new() -> Self {
+        Self {
+            expr: String::new(),
+            ranges: [RangeInput::new("x", "0", "10", "1"), RangeInput::new("y", "0", "10", "1")],
+            two_variables: false,
+            table: None,
+            sorted: None,
+            csv_path: "table.csv".to_string(),
+            status: None,
+        }
+    }
+
+    fn build(&mut self, engine: &CalculatorEngine) {
+        let count = if self.two_variables { 2 } else { 1 };
+        let result = calculator::parse(&self.expr).map_err(|d| d.error).and_then(|expr| {
+            let ranges = self.ranges[..count].iter().map(|r| r.range(engine)).collect::<Result<Vec<_>, _>>()?;
+            engine.table(&expr, &ranges)
+        });
+        self.table = Some(result);
+        self.sorted = None;
+        self.status = None;
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, engine: &CalculatorEngine) {
+        ui.horizontal(|ui| {
+            ui.label("Expression:");
+            ui.add(egui::TextEdit::singleline(&mut self.expr).hint_text("k*x^2"));
+        });
+        for (n, range) in self.ranges.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                if n == 1 {
+                    ui.checkbox(&mut self.two_variables, "and");
+                }
+                ui.add_enabled_ui(n == 0 || self.two_variables, |ui| {
+                    ui.add(egui::TextEdit::singleline(&mut range.var).desired_width(40.0));
+                    ui.label("from");
+                    ui.add(egui::TextEdit::singleline(&mut range.start).desired_width(60.0));
+                    ui.label("to");
+                    ui.add(egui::TextEdit::singleline(&mut range.end).desired_width(60.0));
+                    ui.label("step");
+                    ui.add(egui::TextEdit::singleline(&mut range.step).desired_width(60.0));
+                });
+            });
+        }
+        if ui.button("Build table").clicked() {
+            self.build(engine);
+        }
+
+        let format = engine.number_format();
+        match &mut self.table {
+            None => {}
+            Some(Err(err)) => {
+                ui.colored_label(ui.visuals().error_fg_color, err.to_string());
+            }
+            Some(Ok(table)) => {
+                ui.horizontal(|ui| {
+                    ui.label("CSV file:");
+                    ui.text_edit_singleline(&mut self.csv_path);
+                    if ui.button("Export").clicked() {
+                        self.status = Some(match std::fs::write(&self.csv_path, table.to_csv()) {
+                            Ok(()) => format!("Exported {} rows to {}", table.rows.len(), self.csv_path),
+                            Err(err) => format!("Could not write {}: {}", self.csv_path, err),
+                        });
+                    }
+                    if ui.button("Copy CSV").clicked() {
+                        ui.ctx().copy_text(table.to_csv());
+                    }
+                });
+                if let Some(status) = &self.status {
+                    ui.label(status);
+                }
+
+                // only the rows in view are laid out, a table can have up to MAX_CELLS of them.
+                // Row 0 is the header.
+                let mut clicked = None;
+                let row_height = ui.spacing().interact_size.y;
+                egui::ScrollArea::both().show_rows(ui, row_height, table.rows.len() + 1, |ui, rows| {
+                    egui::Grid::new("table").striped(true).min_row_height(row_height).start_row(rows.start).show(ui, |ui| {
+                        for index in rows {
+                            if index == 0 {
+                                for (column, header) in table.headers.iter().enumerate() {
+                                    let arrow = match self.sorted {
+                                        Some((sorted, false)) if sorted == column => " ⏶",
+                                        Some((sorted, true)) if sorted == column => " ⏷",
+                                        _ => "",
+                                    };
+                                    if ui.button(format!("{}{}", header, arrow)).on_hover_text("Sort by this column").clicked() {
+                                        clicked = Some(column);
+                                    }
+                                }
+                                ui.end_row();
+                                continue;
+                            }
+                            for cell in &table.rows[index - 1] {
+                                match cell {
+                                    Ok(value) => ui.label(value.display_with(format).to_string()),
+                                    Err(err) => ui.colored_label(ui.visuals().error_fg_color, err.to_string()),
+                                };
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                // a second click on the same column turns the order around
+                if let Some(column) = clicked {
+                    let descending = matches!(self.sorted, Some((sorted, false)) if sorted == column);
+                    table.sort_by_column(column, descending);
+                    self.sorted = Some((column, descending));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,7 @@
+// The calculator as a library: an embedding program creates a CalculatorEngine, feeds it the same
+// text the desktop calculator accepts and gets Values back. The GUI and the terminal front ends
+// live in the binary and are only built with the "gui" and "repl" features.
+mod engine;
+
+pub use engine::{CalcError, CalculatorEngine, Curve, Diagnostic, Graph, HistoryEntry, Implicit, Expr, Number, NumberFormat, Relation, Span, Table, TableRange, Token, TokenKind, Value};
+pub use engine::{parse, tokenize};
@@ -1,12 +1,29 @@
-mod engine;
+#[cfg(any(feature = "gui", feature = "repl"))]
+mod autosave;
+mod cli;
+#[cfg(feature = "gui")]
 mod gui;
 
+#[cfg(feature = "gui")]
 use gui::CalculatorApp;
 
 fn main() {
-    //we will need to add a GUI event loop here
-    let mut app = CalculatorApp::new();
-    CalculatorApp::new().run();
+    // "run <file>" executes a script, --repl runs in the terminal, without either the GUI starts
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "run") {
+        std::process::exit(cli::script::run(&args[1..]));
+    }
+
+    #[cfg(feature = "gui")]
+    if !args.iter().any(|arg| arg == "--repl") {
+        CalculatorApp::new().run();
+        return;
+    }
+
+    #[cfg(feature = "repl")]
+    cli::Repl::new().run();
+    #[cfg(not(feature = "repl"))]
+    eprintln!("This build has no REPL, rebuild with the \"repl\" feature");
 
     //just for testing
     /*
@@ -21,10 +38,8 @@ fn main() {
     */
 }
 
-
-
-
-#[cfg(test)]
+#[cfg(all(test, feature = "gui"))]
+#[allow(unused)]
 mod test_basic{
     use super::*;
     #[test]
@@ -52,7 +67,8 @@ mod test_basic{
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "gui"))]
+#[allow(unused)]
 mod test_varables{
     use super::*;
     #[test]
@@ -63,4 +79,1093 @@ mod test_varables{
     fn test_declare_variable_lazy_evaluation(){
         let mut app = CalculatorApp::new();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test_arithmetic{
+    use calculator::{CalcError, CalculatorEngine};
+    use calculator::Value;
+    use calculator::Number;
+    #[test]
+    fn test_add(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(engine.evaluate("2 + 3").unwrap(), vec![Value::Number(Number::from(5))]);
+    }
+    #[test]
+    fn test_sub(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(engine.evaluate("2 - 3 - 4").unwrap(), vec![Value::Number(Number::from(-5))]);
+    }
+    #[test]
+    fn test_mul(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(engine.evaluate("2 + 3 * 4").unwrap(), vec![Value::Number(Number::from(14))]);
+    }
+    #[test]
+    fn test_div(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(engine.evaluate("9 / 2").unwrap(), vec![Value::Number("4.5".parse().unwrap())]);
+    }
+    #[test]
+    fn test_div_by_zero(){
+        let mut engine = CalculatorEngine::new();
+        assert!(matches!(engine.evaluate("1 / 0"), Err(CalcError::DivisionByZero)));
+    }
+    #[test]
+    fn test_invalid_input() {
+        let mut engine = CalculatorEngine::new();
+        assert!(matches!(engine.evaluate("2 $ 3"), Err(CalcError::InvalidExpression(_))));
+    }
+}
+
+#[cfg(test)]
+mod test_assignment{
+    use calculator::CalculatorEngine;
+    use calculator::Value;
+    use calculator::Number;
+    #[test]
+    fn test_declare_variable_using_declared_variable(){
+        let mut engine = CalculatorEngine::new();
+        let results = engine.evaluate("a = 2; b = a + 1; b").unwrap();
+        assert_eq!(results.last(), Some(&Value::Number(Number::from(3))));
+    }
+    #[test]
+    fn test_declare_variable_lazy_evaluation(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("b = a + 1").unwrap();
+        engine.evaluate("a = 2").unwrap();
+        assert_eq!(engine.evaluate("b").unwrap(), vec![Value::Number(Number::from(3))]);
+    }
+}
+
+#[cfg(test)]
+mod test_functions{
+    use calculator::{CalcError, CalculatorEngine};
+    use calculator::Value;
+    use calculator::Number;
+    #[test]
+    fn test_builtin_call(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(engine.evaluate("sqrt(16) + abs(-2)").unwrap(), vec![Value::Number(Number::from(6))]);
+        assert_eq!(engine.evaluate("max(2, 3 * 4)").unwrap(), vec![Value::Number(Number::from(12))]);
+        assert_eq!(engine.evaluate("cos(sin(0))").unwrap(), vec![Value::Number(Number::from(1))]);
+    }
+    #[test]
+    fn test_symbolic_call(){
+        let mut engine = CalculatorEngine::new();
+        let result = engine.evaluate("sin(x)").unwrap();
+        assert!(matches!(&result[0], Value::Expression(_)));
+        engine.evaluate("x = 0").unwrap();
+        assert_eq!(engine.evaluate("sin(x)").unwrap(), vec![Value::Number(Number::from(0))]);
+    }
+    #[test]
+    fn test_call_errors(){
+        let mut engine = CalculatorEngine::new();
+        assert!(matches!(engine.evaluate("sin(1, 2)"), Err(CalcError::ArityMismatch(_, 1, 2))));
+        assert!(matches!(engine.evaluate("nope(1)"), Err(CalcError::UnknownFunction(_))));
+        assert!(matches!(engine.evaluate("sqrt(-1)"), Err(CalcError::DomainError(_))));
+        assert!(matches!(engine.evaluate("1, 2"), Err(CalcError::InvalidExpression(_))));
+    }
+}
+
+#[cfg(test)]
+mod test_user_functions{
+    use calculator::{CalcError, CalculatorEngine};
+    use calculator::Value;
+    use calculator::Number;
+    #[test]
+    fn test_define_and_call(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("f(x, y) = x^2 + y").unwrap();
+        assert_eq!(engine.evaluate("f(3, 1)").unwrap(), vec![Value::Number(Number::from(10))]);
+        assert_eq!(engine.evaluate("a = 1; f(a + 1, a)").unwrap().last(), Some(&Value::Number(Number::from(5))));
+    }
+    #[test]
+    fn test_parameters_shadow_globals(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("x = 100; k = 2").unwrap();
+        engine.evaluate("g(x) = k * x").unwrap();
+        assert_eq!(engine.evaluate("g(3)").unwrap(), vec![Value::Number(Number::from(6))]);
+        engine.evaluate("k = 3").unwrap();
+        assert_eq!(engine.evaluate("g(3)").unwrap(), vec![Value::Number(Number::from(9))]);
+        assert_eq!(engine.evaluate("x").unwrap(), vec![Value::Number(Number::from(100))]);
+    }
+    #[test]
+    fn test_user_function_errors(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("f(x) = x + 1").unwrap();
+        assert!(matches!(engine.evaluate("f(1, 2)"), Err(CalcError::ArityMismatch(_, 1, 2))));
+        assert!(matches!(engine.evaluate("sin(x) = x"), Err(CalcError::InvalidExpression(_))));
+        assert!(matches!(engine.evaluate("h(x, x) = x"), Err(CalcError::InvalidExpression(_))));
+        assert!(matches!(engine.evaluate("h(1) = 2"), Err(CalcError::InvalidExpression(_))));
+    }
+}
+
+#[cfg(test)]
+mod test_exact_arithmetic{
+    use calculator::CalculatorEngine;
+    use calculator::Value;
+    use calculator::Number;
+    #[test]
+    fn test_rationals_stay_exact(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(engine.evaluate("1/3*3").unwrap(), vec![Value::Number(Number::from(1))]);
+        assert_eq!(engine.evaluate("0.1 + 0.2 == 0.3").unwrap(), vec![Value::Bool(true)]);
+        assert_eq!(engine.evaluate("(2/3)^(-2)").unwrap(), vec![Value::Number("2.25".parse().unwrap())]);
+        assert_eq!(engine.evaluate("sqrt(9/4)").unwrap(), vec![Value::Number("1.5".parse().unwrap())]);
+    }
+    #[test]
+    fn test_float_fallback(){
+        let mut engine = CalculatorEngine::new();
+        let result = engine.evaluate("float(1/4) + 1").unwrap();
+        assert!(matches!(&result[0], Value::Number(Number::Float(f)) if *f == 1.25));
+        let result = engine.evaluate("sqrt(2)").unwrap();
+        assert!(matches!(&result[0], Value::Number(Number::Float(_))));
+        assert_eq!(engine.evaluate("x = 1/10; 3 * x == 0.3").unwrap().last(), Some(&Value::Bool(true)));
+    }
+}
+
+#[cfg(test)]
+mod test_big_integers{
+    use calculator::{CalcError, CalculatorEngine};
+    use calculator::Value;
+    use calculator::Number;
+    fn integer(digits: &str) -> Value {
+        Value::Number(digits.parse().unwrap())
+    }
+    #[test]
+    fn test_powers_stay_exact(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(engine.evaluate("2^127 - 1").unwrap(), vec![integer("170141183460469231731687303715884105727")]);
+        assert_eq!(engine.evaluate("2^(-2)").unwrap(), vec![Value::Number("0.25".parse().unwrap())]);
+        assert_eq!(engine.evaluate("(-1)^1000001").unwrap(), vec![Value::Number(Number::from(-1))]);
+        let result = engine.evaluate("2^0.5").unwrap();
+        assert!(matches!(&result[0], Value::Number(Number::Float(_))));
+    }
+    #[test]
+    fn test_huge_powers(){
+        let mut engine = CalculatorEngine::new();
+        let result = engine.evaluate("2^349526").unwrap();
+        assert!(matches!(&result[0], Value::Number(Number::Integer(_))));
+        assert!(result[0].to_string().len() > 100_000);
+        assert!(matches!(engine.evaluate("3^10000000"), Err(CalcError::DomainError(_))));
+        assert!(matches!(engine.evaluate("(2/3)^(-10000000)"), Err(CalcError::DomainError(_))));
+    }
+    #[test]
+    fn test_long_products(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(
+            engine.evaluate("12345678901234567890 * 98765432109876543210").unwrap(),
+            vec![integer("1219326311370217952237463801111263526900")]
+        );
+    }
+    #[test]
+    fn test_combinatorics(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(engine.evaluate("25!").unwrap(), vec![integer("15511210043330985984000000")]);
+        assert_eq!(engine.evaluate("-3! + 1").unwrap(), vec![Value::Number(Number::from(-5))]);
+        assert_eq!(engine.evaluate("binom(50, 25)").unwrap(), vec![integer("126410606437752")]);
+        assert_eq!(engine.evaluate("perm(5, 2) + gcd(12, 18) + lcm(4, 6)").unwrap(), vec![Value::Number(Number::from(38))]);
+        assert!(matches!(engine.evaluate("(1/2)!"), Err(CalcError::DomainError(_))));
+    }
+}
+
+#[cfg(test)]
+mod test_differentiation{
+    use calculator::{CalcError, CalculatorEngine};
+    use calculator::Value;
+    use calculator::Number;
+    #[test]
+    fn test_polynomial_rules(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("d = diff(3*x^3 + 2*x, x)").unwrap();
+        assert_eq!(engine.evaluate("x = 2; d").unwrap().last(), Some(&Value::Number(Number::from(38))));
+        assert_eq!(engine.evaluate("diff(x/(x+1), x)").unwrap(), vec![Value::Number(Number::from(1).checked_div(&Number::from(9)).unwrap())]);
+    }
+    #[test]
+    fn test_chain_rule_and_user_functions(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("f(t) = t^2 + 1").unwrap();
+        assert_eq!(engine.evaluate("x = 3; diff(f(x), x)").unwrap().last(), Some(&Value::Number(Number::from(6))));
+        assert_eq!(engine.evaluate("diff(sin(2*y), y)").unwrap().len(), 1);
+        assert_eq!(engine.evaluate("y = 0; diff(sin(2*y), y)").unwrap().last(), Some(&Value::Number(Number::from(2))));
+    }
+    #[test]
+    fn test_diff_errors(){
+        let mut engine = CalculatorEngine::new();
+        assert!(matches!(engine.evaluate("diff(x^2, 1)"), Err(CalcError::InvalidExpression(_))));
+        assert!(matches!(engine.evaluate("diff(x^2)"), Err(CalcError::ArityMismatch(_, 2, 1))));
+        assert!(matches!(engine.evaluate("diff(t) = t"), Err(CalcError::InvalidExpression(_))));
+    }
+}
+
+#[cfg(test)]
+mod test_simplification{
+    use calculator::CalculatorEngine;
+    use calculator::Value;
+    use calculator::Number;
+    fn simplified(input: &str) -> Value {
+        CalculatorEngine::new().evaluate(input).unwrap().pop().unwrap()
+    }
+    #[test]
+    fn test_like_terms_and_powers(){
+        assert_eq!(simplified("x + x"), simplified("2*x"));
+        assert_eq!(simplified("x - x"), Value::Number(Number::from(0)));
+        assert_eq!(simplified("(x+1)^2 * (x+1)"), simplified("(1+x)^3"));
+        assert_eq!(simplified("(x^2)^3"), simplified("x^6"));
+        assert_eq!(simplified("2*x + 3*y - x + 1 - 4"), simplified("3*y + x - 3"));
+    }
+    #[test]
+    fn test_cancellation(){
+        assert_eq!(simplified("x*x/x"), simplified("x"));
+        assert_eq!(simplified("x*y/(2*y)"), simplified("x/2"));
+        assert_eq!(simplified("x + x == 2*x"), Value::Bool(true));
+    }
+    #[test]
+    fn test_canonical_order(){
+        assert_eq!(simplified("b*a + c"), simplified("c + a*b"));
+        assert_eq!(simplified("1 + x^2 + x"), simplified("x + 1 + x^2"));
+        assert_eq!(simplified("-(2*x)"), simplified("-2*x"));
+    }
+}
+
+#[cfg(test)]
+mod test_solve{
+    use calculator::{CalcError, CalculatorEngine};
+    use calculator::Value;
+    fn solve(input: &str) -> Value {
+        CalculatorEngine::new().evaluate(input).unwrap().pop().unwrap()
+    }
+    #[test]
+    fn test_linear_and_quadratic(){
+        assert_eq!(solve("solve(2*x + 3 == 7, x)"), solve("solve(x == 2, x)"));
+        assert_eq!(format!("{:?}", solve("solve(x^2 == 4, x)")), "Expression(List([Number(Integer(-2)), Number(Integer(2))]))");
+        assert_eq!(format!("{:?}", solve("solve((x+1)^2 == 9, x)")), "Expression(List([Number(Integer(-4)), Number(Integer(2))]))");
+        assert_eq!(format!("{:?}", solve("solve(x^2 + 1 == 0, x)")), "Expression(List([]))");
+    }
+    #[test]
+    fn test_rearranging_formulas(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("s = solve(v == u + a*t, a)").unwrap();
+        assert_eq!(engine.evaluate("v = 10; u = 4; t = 3; s").unwrap().pop(), Some(solve("solve(x == 2, x)")));
+    }
+    #[test]
+    fn test_numeric_fallback(){
+        let roots = format!("{:?}", solve("solve(cos(x) == x, x)"));
+        assert!(roots.starts_with("Expression(List([Number(Float(0.739085133215"));
+        let roots = format!("{:?}", solve("solve(sin(x) == 0, x, -4, 4)"));
+        assert_eq!(roots.matches("Number(").count(), 3);
+        assert!(matches!(CalculatorEngine::new().evaluate("solve(x == x, x)"), Err(CalcError::InvalidExpression(_))));
+        assert!(matches!(CalculatorEngine::new().evaluate("solve(x^2, 3)"), Err(CalcError::InvalidExpression(_))));
+    }
+    #[test]
+    fn test_bounds(){
+        assert_eq!(solve("solve(x^2 == 4, x, 0, 5)").to_string(), "[2]");
+        assert_eq!(solve("solve(x^2 == 4, x, 3, 5)").to_string(), "[]");
+        assert_eq!(solve("solve(x^2 == 2, x, -5, 0)").to_string(), solve("solve(x == -sqrt(2), x)").to_string());
+        assert!(matches!(CalculatorEngine::new().evaluate("solve(x^2 == 4, x, 5, -5)"), Err(CalcError::InvalidExpression(_))));
+        assert!(matches!(CalculatorEngine::new().evaluate("solve(x^2 == 4, y)"), Err(CalcError::InvalidExpression(_))));
+    }
+    #[test]
+    fn test_lists_are_not_numbers(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("s = solve(x^2 == 4, x)").unwrap();
+        for input in ["s + 1", "s*2", "s^2", "-s", "1 - s", "s/2"] {
+            assert!(matches!(engine.evaluate(input), Err(CalcError::InvalidExpression(_))), "{}", input);
+        }
+        assert_eq!(engine.evaluate("s").unwrap()[0].to_string(), "[-2, 2]");
+    }
+}
+
+#[cfg(test)]
+mod test_display{
+    use calculator::{CalcError, CalculatorEngine, NumberFormat};
+    fn shown(input: &str) -> String {
+        let mut engine = CalculatorEngine::new();
+        let values = engine.evaluate(input).unwrap();
+        engine.format_values(&values)
+    }
+    #[test]
+    fn test_overflow(){
+        assert_eq!(shown("exp(1000)"), "∞");
+        assert_eq!(shown("-exp(1000)"), "-∞");
+        let text = shown("exp(1000)*x");
+        assert!(!text.contains("inf"), "{}", text);
+        // what is shown cannot be typed back in as a name
+        assert!(CalculatorEngine::new().evaluate(&text).is_err());
+    }
+    #[test]
+    fn test_expressions(){
+        assert_eq!(shown("2 + 3"), "5");
+        assert_eq!(shown("x^2 + 2*x + 1"), "x^2 + 2*x + 1");
+        assert_eq!(shown("x - y"), "x - y");
+        assert_eq!(shown("x*(y + 1)"), "x*(y + 1)");
+        assert_eq!(shown("x/(2*y)"), "x/(2*y)");
+        assert_eq!(shown("(x + 1)^2"), "(x + 1)^2");
+        assert_eq!(shown("sin(x)^(1/2)"), "sin(x)^(1/2)");
+        assert_eq!(shown("-(x + y)"), "-(x + y)");
+        assert_eq!(shown("x - 2*y"), "x - 2*y");
+        assert_eq!(shown("2 == 2; x"), "true; x");
+        assert_eq!(shown("solve(x^2 == 4, x)"), "[-2, 2]");
+    }
+    #[test]
+    fn test_round_trip(){
+        let inputs = ["x^2 - 3*x + 1/3", "(a - b)/(c + d)", "-(x^2)", "2^(x^2)", "(1/3)^x", "x - (y - z)", "atan2(y, -x)*2", "float(1/3)", "2^100"];
+        for input in inputs {
+            let mut engine = CalculatorEngine::new();
+            let first = engine.evaluate(input).unwrap();
+            let printed = engine.format_values(&first);
+            assert_eq!(engine.evaluate(&printed).unwrap(), first, "{} printed as {}", input, printed);
+        }
+    }
+    #[test]
+    fn test_number_format(){
+        let mut engine = CalculatorEngine::new();
+        let values = engine.evaluate("1/3; 2/3; float(2); 1/8").unwrap();
+        assert_eq!(engine.format_values(&values), "1/3; 2/3; 2; 1/8");
+        let mut format = NumberFormat::default();
+        format.fractions = false;
+        format.precision = Some(3);
+        engine.set_number_format(format);
+        assert_eq!(engine.format_values(&values), "0.333; 0.667; 2; 0.125");
+        format.precision = None;
+        engine.set_number_format(format);
+        let values = engine.evaluate("-1/3").unwrap();
+        assert_eq!(engine.format_values(&values), "-0.333333333333");
+    }
+    #[test]
+    fn test_errors(){
+        assert_eq!(CalcError::DivisionByZero.to_string(), "Division by zero");
+        assert_eq!(CalcError::ArityMismatch("sin".into(), 1, 2).to_string(), "sin expects 1 argument, got 2");
+    }
+}
+
+#[cfg(test)]
+mod test_markup{
+    use calculator::CalculatorEngine;
+    fn latex(input: &str) -> String {
+        let mut engine = CalculatorEngine::new();
+        let values = engine.evaluate(input).unwrap();
+        engine.latex_values(&values)
+    }
+    fn mathml(input: &str) -> String {
+        let mut engine = CalculatorEngine::new();
+        let values = engine.evaluate(input).unwrap();
+        engine.mathml_values(&values)
+    }
+    #[test]
+    fn test_latex(){
+        assert_eq!(latex("1/3"), "\\frac{1}{3}");
+        assert_eq!(latex("x^2 + 2*x - 1"), "x^{2} + 2 x - 1");
+        assert_eq!(latex("(x + 1)/(2*y)"), "\\frac{x + 1}{2 y}");
+        assert_eq!(latex("sin(x)^2 + sqrt(y)"), "\\sin\\left(x\\right)^{2} + \\sqrt{y}");
+        assert_eq!(latex("-(a + b)"), "-\\left(a + b\\right)");
+        assert_eq!(latex("(x + 1)^(1/2)"), "\\left(x + 1\\right)^{\\frac{1}{2}}");
+        assert_eq!(latex("alpha*x_1 + speed"), "\\alpha x_{1} + \\mathrm{speed}");
+        assert_eq!(latex("3*4; 1 == 1"), "12;\\quad \\text{true}");
+    }
+    #[test]
+    fn test_mathml(){
+        let math = |body: &str| format!("<math xmlns=\"http://www.w3.org/1998/Math/MathML\">{}</math>", body);
+        assert_eq!(mathml("-1/3"), math("<mrow><mo>-</mo><mfrac><mn>1</mn><mn>3</mn></mfrac></mrow>"));
+        assert_eq!(mathml("x^2 - y"), math("<mrow><msup><mi>x</mi><mn>2</mn></msup><mo>-</mo><mi>y</mi></mrow>"));
+        assert_eq!(mathml("2*cos(x)"), math("<mrow><mn>2</mn><mo>&#x2062;</mo><mrow><mi>cos</mi><mo>&#x2061;</mo><mrow><mo>(</mo><mi>x</mi><mo>)</mo></mrow></mrow></mrow>"));
+    }
+}
+
+#[cfg(test)]
+mod test_session{
+    use calculator::CalculatorEngine;
+    #[test]
+    fn test_listing_and_clear(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("b = 2; a = b + 1; f(x) = x*a").unwrap();
+        let names: Vec<String> = engine.variables().into_iter().map(|(name, value)| format!("{} = {}", name, value)).collect();
+        assert_eq!(names, vec!["a = 3", "b = 2"]);
+        let functions = engine.functions();
+        assert_eq!(functions.len(), 1);
+        assert_eq!(format!("{}({}) = {}", functions[0].0, functions[0].1.join(", "), functions[0].2), "f(x) = x*a");
+        engine.clear();
+        assert!(engine.variables().is_empty() && engine.functions().is_empty());
+        assert_eq!(engine.evaluate("a").unwrap()[0].to_string(), "a");
+    }
+}
+
+#[cfg(test)]
+mod test_script{
+    use crate::cli::script::split_statements;
+    fn split(source: &str) -> Vec<(usize, usize, String)> {
+        split_statements(source).into_iter().map(|s| (s.line, s.column, s.text)).collect()
+    }
+    #[test]
+    fn test_split_statements(){
+        let source = "# header\na = 1  # comment\n\n  f(x) = max(x,\n    0)\nb = a \\\n + 1\n";
+        assert_eq!(split(source), vec![
+            (2, 1, "a = 1".to_string()),
+            (4, 3, "f(x) = max(x, 0)".to_string()),
+            (6, 1, "b = a + 1".to_string()),
+        ]);
+        assert_eq!(split("x = (1 +\n"), vec![(1, 1, "x = (1 +".to_string())]);
+        assert_eq!(split("save \"a#b.json\" # done\n"), vec![(1, 1, "save \"a#b.json\"".to_string())]);
+    }
+    #[test]
+    fn test_positions(){
+        let statements = split_statements("  f(x) = max(x,\n    1/0)\n");
+        assert_eq!(statements[0].position(0), (1, 3));
+        assert_eq!(statements[0].position(9), (1, 12));
+        // "1/0" starts after "f(x) = max(x, " in the joined text
+        assert_eq!(statements[0].position(14), (2, 5));
+    }
+}
+
+#[cfg(test)]
+mod test_library_api{
+    use calculator::{parse, CalcError, CalculatorEngine, Expr, Number, Value};
+    #[test]
+    fn test_parse_and_eval(){
+        let mut engine = CalculatorEngine::new();
+        let expr = parse("x^2 + 1").unwrap();
+        assert_eq!(engine.eval(&expr).unwrap().to_string(), "x^2 + 1");
+        engine.set_variable("x", 3).unwrap();
+        assert_eq!(engine.eval(&expr).unwrap(), Value::Number(Number::from(10)));
+        assert!(matches!(parse("x = 1").unwrap_err().error, CalcError::InvalidExpression(_)));
+        assert!(matches!(parse("1; 2").unwrap_err().error, CalcError::InvalidExpression(_)));
+    }
+    #[test]
+    fn test_variables(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(engine.get_variable("y"), None);
+        engine.set_variable("y", parse("2*z").unwrap()).unwrap();
+        engine.evaluate("z = 4").unwrap();
+        assert_eq!(engine.evaluate("y").unwrap(), vec![Value::Number(Number::from(8))]);
+        assert_eq!(engine.get_variable("y").unwrap().to_string(), "2*z");
+        assert!(engine.set_variable("sin", Expr::from(1.5)).is_err());
+        assert!(engine.set_variable("2x", 1).is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_diagnostics{
+    use calculator::{CalcError, CalculatorEngine, Span};
+    fn rendered(input: &str) -> String {
+        CalculatorEngine::new().evaluate_with_diagnostics(input).unwrap_err().render(input)
+    }
+    fn span(input: &str) -> Option<Span> {
+        CalculatorEngine::new().evaluate_with_diagnostics(input).unwrap_err().span
+    }
+    #[test]
+    fn test_token_spans(){
+        let tokens = calculator::tokenize("ab += 1.5").unwrap();
+        let spans: Vec<(usize, usize)> = tokens.iter().map(|t| (t.span.start, t.span.end)).collect();
+        assert_eq!(spans, vec![(0, 2), (3, 5), (6, 9)]);
+    }
+    #[test]
+    fn test_parse_errors(){
+        assert_eq!(rendered("2 + $"), "2 + $\n    ^ Invalid expression: Unexpected character '$'");
+        assert_eq!(span("1 + (2 * 3"), Some(Span::new(4, 5)));
+        assert_eq!(span("2 *"), Some(Span::new(2, 3)));
+        assert_eq!(span("x; 3 4"), Some(Span::new(5, 6)));
+        assert_eq!(span("sin(x) = 2"), Some(Span::new(0, 3)));
+        assert_eq!(span("f(1) = 2"), Some(Span::new(2, 3)));
+    }
+    #[test]
+    fn test_evaluation_errors(){
+        assert_eq!(rendered("1 + 2/(3 - 3)"), "1 + 2/(3 - 3)\n    ^~~~~~~~~ Division by zero");
+        assert_eq!(span("x = 2*sqrt(-1) + 1"), Some(Span::new(6, 14)));
+        assert_eq!(span("1; atan2(1, 2, 3)"), Some(Span::new(3, 17)));
+        assert_eq!(span("y = 0; x = 1; x /= y"), Some(Span::new(14, 20)));
+        let mut engine = CalculatorEngine::new();
+        assert!(matches!(engine.evaluate("1/0"), Err(CalcError::DivisionByZero)));
+    }
+}
+
+#[cfg(test)]
+mod test_parser{
+    use calculator::{CalculatorEngine, Span};
+    fn show(input: &str) -> String {
+        let mut engine = CalculatorEngine::new();
+        let values = engine.evaluate(input).unwrap();
+        engine.format_values(&values)
+    }
+    fn message(input: &str) -> (String, Option<Span>) {
+        let err = CalculatorEngine::new().evaluate_with_diagnostics(input).unwrap_err();
+        (err.to_string(), err.span)
+    }
+    #[test]
+    fn test_precedence(){
+        assert_eq!(show("2^3^2"), "512");
+        assert_eq!(show("2^-2"), "1/4");
+        assert_eq!(show("-x^2"), "-x^2");
+        assert_eq!(show("2*-3; -2!; 3!^2"), "-6; -2; 36");
+        assert_eq!(show("1 + 2*3 == 7"), "true");
+    }
+    #[test]
+    fn test_statements(){
+        assert_eq!(show("x = 1; x += 2; x *= 3"), "1; 3; 9");
+        assert_eq!(show("g(a, b) = a*b; g(2, 3)"), "a*b; 6");
+        assert_eq!(show("1;"), "1");
+    }
+    #[test]
+    fn test_grammar_errors(){
+        assert_eq!(message("3 4"), ("Invalid expression: Expected an operator between two values".to_string(), Some(Span::new(2, 3))));
+        assert_eq!(message("+"), ("Invalid expression: Expected a value after '+'".to_string(), Some(Span::new(0, 1))));
+        assert_eq!(message("2 )"), ("Invalid expression: Unmatched ')'".to_string(), Some(Span::new(2, 3))));
+        assert_eq!(message("(1"), ("Invalid expression: Missing ')' to close this '('".to_string(), Some(Span::new(0, 1))));
+        assert_eq!(message("()").0, "Invalid expression: Expected a value inside the parentheses");
+        assert_eq!(message("f(1,)").0, "Invalid expression: Expected an argument after ',' in call to f");
+        assert_eq!(message("1 = 2").0, "Invalid expression: Cannot assign to a non variable expression");
+    }
+}
+
+#[cfg(test)]
+mod test_cycles{
+    use calculator::{CalcError, CalculatorEngine, Number, Value};
+    fn cycle(engine: &mut CalculatorEngine, input: &str) -> Vec<String> {
+        match engine.evaluate(input) {
+            Err(CalcError::CyclicDefinition(path)) => path,
+            other => panic!("expected a cycle from {}, got {:?}", input, other),
+        }
+    }
+    #[test]
+    fn test_cycle_paths(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("a = b + 1").unwrap();
+        assert_eq!(cycle(&mut engine, "b = a + 1"), vec!["b", "a", "b"]);
+        engine.evaluate("c = d*2; d = e^2").unwrap();
+        assert_eq!(cycle(&mut engine, "e = c"), vec!["e", "c", "d", "e"]);
+        assert_eq!(cycle(&mut engine, "y = y + 1"), vec!["y", "y"]);
+        assert_eq!(cycle(&mut engine, "y += 1"), vec!["y", "y"]);
+        assert!(matches!(engine.set_variable("b", calculator::parse("a").unwrap()), Err(CalcError::CyclicDefinition(_))));
+        // nothing of a rejected assignment is kept
+        assert_eq!(engine.get_variable("b"), None);
+        assert_eq!(engine.get_variable("y"), None);
+    }
+    #[test]
+    fn test_updates_are_not_cycles(){
+        let mut engine = CalculatorEngine::new();
+        let values = engine.evaluate("x = 3; x = x + 1; x += x").unwrap();
+        assert_eq!(values[2], Value::Number(Number::from(8)));
+        engine.evaluate("a = b + 1; b = 2; b = a").unwrap();
+        assert_eq!(engine.get_variable("b"), Some(Value::Number(Number::from(3))));
+    }
+    #[test]
+    fn test_recursive_functions(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(cycle(&mut engine, "f(x) = f(x) + 1"), vec!["f", "f"]);
+        engine.evaluate("h(x) = k(x)").unwrap();
+        assert_eq!(cycle(&mut engine, "k(x) = h(x)"), vec!["k", "h", "k"]);
+        engine.evaluate("g(x) = 2*x; p(x) = g(x) + h(x)").unwrap();
+        assert_eq!(cycle(&mut engine, "g(x) = p(x)"), vec!["g", "p", "g"]);
+        // nothing of a rejected definition is kept
+        assert_eq!(engine.evaluate("g(2)").unwrap(), vec![Value::Number(Number::from(4))]);
+        assert_eq!(engine.functions().len(), 3);
+        // calling another function twice is fine
+        engine.evaluate("q(x) = g(x) + g(g(x))").unwrap();
+        assert_eq!(engine.evaluate("q(1)").unwrap(), vec![Value::Number(Number::from(6))]);
+    }
+    #[test]
+    fn test_depth_limit(){
+        let mut engine = CalculatorEngine::new();
+        let nested = "-(".repeat(1000) + "1" + &")".repeat(1000);
+        assert!(matches!(engine.evaluate(&nested), Err(CalcError::RecursionLimit(_))));
+        engine.evaluate("f0(x) = x").unwrap();
+        for i in 1..300 {
+            engine.evaluate(&format!("f{}(x) = f{}(x) + 1", i, i - 1)).unwrap();
+        }
+        assert!(matches!(engine.evaluate("f299(0)"), Err(CalcError::RecursionLimit(_))));
+        assert!(engine.evaluate("f20(0)").is_ok());
+        // long sums are flat and do not count as nesting
+        let sum = vec!["1"; 2000].join(" + ");
+        assert_eq!(engine.evaluate(&sum).unwrap(), vec![Value::Number(Number::from(2000))]);
+        // and neither do products, differences and quotients
+        let product = vec!["2"; 141].join("*");
+        assert_eq!(engine.evaluate(&product).unwrap(), vec![Value::Number(Number::from(2).pow(&Number::from(141)).unwrap())]);
+        let difference = vec!["1"; 500].join("-");
+        assert_eq!(engine.evaluate(&difference).unwrap(), vec![Value::Number(Number::from(-498))]);
+        let quotient = vec!["1"; 500].join("/");
+        assert_eq!(engine.evaluate(&quotient).unwrap(), vec![Value::Number(Number::from(1))]);
+        let mixed = vec!["x"; 200].join("*") + " - " + &vec!["1"; 200].join(" - ") + " / 2 / 2";
+        assert!(engine.evaluate(&mixed).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod test_bindings{
+    use calculator::{CalcError, CalculatorEngine, Number, Value};
+    fn number(engine: &CalculatorEngine, name: &str) -> Value {
+        engine.get_variable(name).unwrap_or_else(|| panic!("{} is not defined", name))
+    }
+    #[test]
+    fn test_dependents_follow_changes(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("b := a + 1; c := b*2; d = b").unwrap();
+        assert!(engine.invalidated().is_empty());
+        engine.evaluate("a = 5").unwrap();
+        assert_eq!(number(&engine, "b"), Value::Number(Number::from(6)));
+        assert_eq!(number(&engine, "c"), Value::Number(Number::from(12)));
+        assert_eq!(engine.invalidated(), ["b", "c"]);
+        // d was a plain assignment and kept what b was back then
+        assert_eq!(engine.get_variable("d").unwrap().to_string(), "a + 1");
+        engine.evaluate("b = 1").unwrap();
+        assert_eq!(engine.binding("b"), None);
+        assert_eq!(number(&engine, "c"), Value::Number(Number::from(2)));
+        engine.evaluate("a = 7").unwrap();
+        assert!(engine.invalidated().is_empty());
+        assert_eq!(engine.dependencies("c").into_iter().collect::<Vec<_>>(), ["b"]);
+    }
+    #[test]
+    fn test_binding_errors(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("b := a + 1; c := b*2").unwrap();
+        match engine.evaluate("a := c") {
+            Err(CalcError::CyclicDefinition(path)) => assert_eq!(path, ["a", "c", "b", "a"]),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+        engine.evaluate("a = 2").unwrap();
+        assert!(matches!(engine.evaluate("x := x + 1"), Err(CalcError::CyclicDefinition(_))));
+        // a dependent that cannot be recomputed rejects the change and leaves everything as it was
+        engine.evaluate("e := 1/a").unwrap();
+        assert!(matches!(engine.evaluate("a = 0"), Err(CalcError::DivisionByZero)));
+        assert_eq!(number(&engine, "a"), Value::Number(Number::from(2)));
+        assert_eq!(number(&engine, "b"), Value::Number(Number::from(3)));
+    }
+    #[test]
+    fn test_redefined_functions(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("a = 1; g(t) = t; h(t) = g(t) + 1; b := g(a); c := h(a); d := b*2").unwrap();
+        engine.evaluate("g(t) = t*10").unwrap();
+        assert_eq!(number(&engine, "b"), Value::Number(Number::from(10)));
+        assert_eq!(number(&engine, "c"), Value::Number(Number::from(11)));
+        assert_eq!(number(&engine, "d"), Value::Number(Number::from(20)));
+        assert_eq!(engine.invalidated(), ["b", "c", "d"]);
+        // a definition the bindings cannot be recomputed with is rejected
+        assert!(matches!(engine.evaluate("g(t) = 1/(t - 1)"), Err(CalcError::DivisionByZero)));
+        assert_eq!(number(&engine, "b"), Value::Number(Number::from(10)));
+        assert_eq!(engine.evaluate("g(2)").unwrap(), vec![Value::Number(Number::from(20))]);
+    }
+}
+
+#[cfg(test)]
+mod test_persistence{
+    use calculator::{CalcError, CalculatorEngine, Number, Value};
+    #[test]
+    fn test_round_trip(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("a = 1/3; b := a + 1; c = sqrt(2)*x; f(x, y) = x^2 + a*y").unwrap();
+        let mut format = engine.number_format();
+        format.precision = Some(4);
+        engine.set_number_format(format);
+        let json = engine.session_json().unwrap();
+
+        let mut restored = CalculatorEngine::new();
+        restored.load_session_json(&json).unwrap();
+        assert_eq!(restored.variables(), engine.variables());
+        assert_eq!(restored.functions(), engine.functions());
+        assert_eq!(restored.history(), engine.history());
+        assert_eq!(restored.number_format(), format);
+        assert_eq!(restored.binding("b").unwrap().to_string(), "a + 1");
+        // bindings are live again after loading
+        restored.evaluate("a = 2").unwrap();
+        assert_eq!(restored.get_variable("b"), Some(Value::Number(Number::from(3))));
+        assert_eq!(restored.evaluate("f(1, 1)").unwrap(), vec![Value::Number(Number::from(3))]);
+    }
+    #[test]
+    fn test_bad_files(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("x = 1").unwrap();
+        assert!(matches!(engine.load_session_json("{"), Err(CalcError::FileError(_))));
+        let bad_value = r#"{"version": 1, "settings": {"fractions": true, "precision": null}, "variables": {"y": "2 +"}}"#;
+        assert!(matches!(engine.load_session_json(bad_value), Err(CalcError::FileError(_))));
+        let cyclic = r#"{"version": 1, "settings": {"fractions": true, "precision": null}, "variables": {"a": "b", "b": "a"}}"#;
+        assert!(matches!(engine.load_session_json(cyclic), Err(CalcError::CyclicDefinition(_))));
+        let recursive = r#"{"version": 2, "settings": {"fractions": true, "precision": null}, "variables": {}, "functions": {"f": {"params": ["x"], "body": "f(x) + 1"}}}"#;
+        assert!(matches!(engine.load_session_json(recursive), Err(CalcError::CyclicDefinition(_))));
+        assert_eq!(engine.get_variable("x"), Some(Value::Number(Number::from(1))));
+    }
+    #[test]
+    fn test_lists(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("s = solve(x^2 == 2, x)").unwrap();
+        let json = engine.session_json().unwrap();
+        let mut restored = CalculatorEngine::new();
+        restored.load_session_json(&json).unwrap();
+        assert_eq!(restored.variables(), engine.variables());
+        assert_eq!(restored.get_variable("s").unwrap().to_string(), engine.get_variable("s").unwrap().to_string());
+    }
+    #[test]
+    fn test_history_results(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("solve(x^2 == 4, x); f(x) = x < 2; g(x) = x == 1").unwrap();
+        let json = engine.session_json().unwrap();
+        let mut restored = CalculatorEngine::new();
+        restored.load_session_json(&json).unwrap();
+        assert_eq!(restored.history(), engine.history());
+        // files written before lists could be saved have them as [..], they come back as text
+        let old = r#"{"version": 2, "settings": {"fractions": true, "precision": null}, "variables": {},
+            "history": [{"input": "solve(x^2 == 4, x)", "result": {"value": "[-2, 2]"}}]}"#;
+        restored.load_session_json(old).unwrap();
+        assert_eq!(restored.history()[0].value, Value::Text("[-2, 2]".to_string()));
+    }
+    #[test]
+    fn test_infinite_results(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("exp(1000); 2 + 3").unwrap();
+        let json = engine.session_json().unwrap();
+        let mut restored = CalculatorEngine::new();
+        restored.load_session_json(&json).unwrap();
+        assert_eq!(restored.history()[0].value, Value::Text("∞".to_string()));
+        assert_eq!(restored.evaluate("$2").unwrap(), vec![Value::Number(Number::from(5))]);
+        // a variable still has to be a number to be saved
+        engine.evaluate("big = exp(1000)").unwrap();
+        assert!(matches!(engine.session_json(), Err(CalcError::FileError(_))));
+    }
+    #[test]
+    fn test_save_and_load_commands(){
+        let path = std::env::temp_dir().join(format!("calc_session_test_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut engine = CalculatorEngine::new();
+        let saved = engine.evaluate(&format!("r = 2^70; save \"{}\"", path)).unwrap();
+        assert_eq!(saved[1].to_string(), format!("Saved session to {}", path));
+        engine.evaluate("r = 0").unwrap();
+        engine.evaluate(&format!("load \"{}\"", path)).unwrap();
+        assert_eq!(engine.evaluate("r").unwrap()[0].to_string(), "1180591620717411303424");
+        std::fs::remove_file(path).unwrap();
+        assert!(matches!(engine.evaluate(&format!("load \"{}\"", path)), Err(CalcError::FileError(_))));
+    }
+}
+
+#[cfg(test)]
+mod test_history{
+    use calculator::{CalcError, CalculatorEngine, Number, Value};
+    fn value(engine: &mut CalculatorEngine, input: &str) -> String {
+        engine.evaluate(input).unwrap().last().unwrap().to_string()
+    }
+    #[test]
+    fn test_results_are_recorded(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("x = 2; x^2 ;").unwrap();
+        assert!(engine.evaluate("1/0").is_err());
+        let history = engine.history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].input, "x = 2");
+        assert_eq!(history[1].input, "x^2");
+        assert_eq!(history[1].value, Value::Number(Number::from(4)));
+    }
+    #[test]
+    fn test_references(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(value(&mut engine, "2 + 3; ans*2"), "10");
+        assert_eq!(value(&mut engine, "_ + $1"), "15");
+        assert_eq!(value(&mut engine, "y + $2"), "y + 10");
+        // bindings and functions keep the result they were written with
+        assert_eq!(value(&mut engine, "b := a + ans; f(t) = t*$1"), "t*5");
+        engine.evaluate("a = 1").unwrap();
+        assert_eq!(engine.get_variable("b").unwrap().to_string(), "y + 11");
+    }
+    #[test]
+    fn test_reference_errors(){
+        let mut engine = CalculatorEngine::new();
+        let err = engine.evaluate_with_diagnostics("ans").unwrap_err();
+        assert!(matches!(err.error, CalcError::InvalidExpression(_)));
+        engine.evaluate("1 == 1").unwrap();
+        let err = engine.evaluate_with_diagnostics("2 + $7").unwrap_err();
+        assert_eq!(err.render("2 + $7"), "2 + $7\n    ^~ Invalid expression: There is no result $7 in the history");
+        assert!(engine.evaluate("ans + 1").is_err());
+        assert!(engine.evaluate("ans = 1").is_err());
+        assert!(engine.set_variable("_", 1).is_err());
+    }
+    #[test]
+    fn test_reference_to_an_equation(){
+        // the result of defining f is its body, an equation that ans brings back as a whole
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("f(x) = x == 2").unwrap();
+        assert_eq!(engine.evaluate("ans").unwrap(), [Value::Bool(false)]);
+        assert_eq!(engine.evaluate("$1").unwrap(), [Value::Bool(false)]);
+        engine.evaluate("g(x) = x < 2").unwrap();
+        assert!(engine.evaluate_with_diagnostics("ans").is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_undo{
+    use calculator::{CalcError, CalculatorEngine, Number, Value};
+    fn number(n: i64) -> Option<Value> {
+        Some(Value::Number(Number::from(n)))
+    }
+    #[test]
+    fn test_undo_and_redo(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("x = 5; f(t) = t + x").unwrap();
+        engine.evaluate("x *= 0").unwrap();
+        engine.evaluate("x + 1").unwrap();
+        assert_eq!(engine.evaluate("undo").unwrap()[0].to_string(), "Undid x *= 0");
+        assert_eq!(engine.get_variable("x"), number(5));
+        engine.evaluate("undo").unwrap();
+        assert_eq!(engine.get_variable("x"), None);
+        assert!(engine.functions().is_empty());
+        assert!(engine.evaluate("undo").is_err());
+        engine.evaluate("redo").unwrap();
+        assert_eq!(engine.evaluate("f(1)").unwrap()[0], Value::Number(Number::from(6)));
+        // a new change drops what could have been redone
+        engine.evaluate("x = 7").unwrap();
+        assert!(!engine.can_redo());
+        engine.clear();
+        engine.undo().unwrap();
+        assert_eq!(engine.get_variable("x"), number(7));
+        engine.set_variable("x", 1).unwrap();
+        engine.undo().unwrap();
+        assert_eq!(engine.get_variable("x"), number(7));
+    }
+    #[test]
+    fn test_failed_input_is_rolled_back(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("y = 2").unwrap();
+        let history = engine.history().len();
+        assert!(matches!(engine.evaluate("y = 3; z = 1/0; w = 4"), Err(CalcError::DivisionByZero)));
+        assert_eq!(engine.get_variable("y"), number(2));
+        assert_eq!(engine.get_variable("z"), None);
+        assert_eq!(engine.history().len(), history);
+        // the failed input is no step of its own
+        engine.evaluate("undo").unwrap();
+        assert_eq!(engine.get_variable("y"), None);
+        assert!(engine.evaluate("undo; y = 1").is_err());
+    }
+    #[test]
+    fn test_restored_session_is_no_step(){
+        let path = std::env::temp_dir().join(format!("calc_restore_test_{}.json", std::process::id()));
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("a = 4; f(t) = t + a").unwrap();
+        engine.save_session(&path).unwrap();
+        let mut restored = CalculatorEngine::new();
+        restored.restore_session(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!restored.can_undo());
+        assert!(restored.evaluate("undo").is_err());
+        assert_eq!(restored.get_variable("a"), number(4));
+        assert_eq!(restored.functions().len(), 1);
+    }
+    #[test]
+    fn test_failed_input_keeps_the_history(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("a = 1; a + 1").unwrap();
+        assert!(engine.evaluate("b = 2; 1/0").is_err());
+        assert_eq!(engine.history().len(), 2);
+        assert!(engine.evaluate("reset; 1/0").is_err());
+        assert_eq!(engine.history().len(), 2);
+        assert_eq!(engine.get_variable("a"), number(1));
+        // the reset itself and c are all that is left after one that works
+        engine.evaluate("reset; c").unwrap();
+        assert_eq!(engine.history().len(), 2);
+    }
+    #[test]
+    fn test_format_is_not_undone(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("a = 1/2").unwrap();
+        let mut format = engine.number_format();
+        format.fractions = false;
+        engine.set_number_format(format);
+        engine.evaluate("undo").unwrap();
+        assert_eq!(engine.number_format(), format);
+        assert!(!engine.can_undo());
+    }
+}
+#[cfg(test)]
+mod test_variables{
+    use calculator::{CalcError, CalculatorEngine, Number, NumberFormat, Value};
+    fn number(n: i64) -> Option<Value> {
+        Some(Value::Number(Number::from(n)))
+    }
+    #[test]
+    fn test_list_and_inspect(){
+        let mut engine = CalculatorEngine::new();
+        assert_eq!(engine.evaluate("vars").unwrap()[0].to_string(), "No variables or functions defined");
+        engine.evaluate("a = 5; b := a + 1; c := b * 2; f(x) = x + a").unwrap();
+        assert_eq!(engine.evaluate("vars").unwrap()[0].to_string(), "a = 5\nb := a + 1 = 6\nc := b*2 = 12\nf(x) = x + a");
+        assert_eq!(engine.evaluate("inspect b").unwrap()[0].to_string(), "b := a + 1 = 6\ndepends on a\nused by c");
+        assert_eq!(engine.dependents("a").into_iter().collect::<Vec<_>>(), ["b"]);
+        assert!(matches!(engine.evaluate("inspect nothing"), Err(CalcError::UndefinedVariable(_))));
+        // only a statement of bare names is a command
+        engine.evaluate("vars = 2").unwrap();
+        assert_eq!(engine.evaluate("vars + 1").unwrap()[0], Value::Number(Number::from(3)));
+    }
+    #[test]
+    fn test_current_values(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("y = 2*z; z = 4").unwrap();
+        assert_eq!(engine.evaluate("vars").unwrap()[0].to_string(), "y = 2*z = 8\nz = 4");
+        assert_eq!(engine.evaluate("inspect y").unwrap()[0].to_string(), "y = 2*z = 8\ndepends on z");
+        engine.evaluate("unset z").unwrap();
+        assert_eq!(engine.evaluate("inspect y").unwrap()[0].to_string(), "y = 2*z\ndepends on z");
+    }
+    #[test]
+    fn test_unset(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("a = 5; b := a + 1; f(x) = x").unwrap();
+        engine.evaluate("unset a f").unwrap();
+        assert_eq!(engine.get_variable("a"), None);
+        assert!(engine.functions().is_empty());
+        // the binding stays and follows a becoming undefined
+        assert_eq!(engine.get_variable("b").unwrap().to_string(), "a + 1");
+        assert_eq!(engine.invalidated(), ["b"]);
+        assert!(matches!(engine.evaluate("unset a"), Err(CalcError::UndefinedVariable(_))));
+        engine.evaluate("undo").unwrap();
+        assert_eq!(engine.get_variable("b"), number(6));
+        engine.unset("b").unwrap();
+        assert_eq!(engine.get_variable("b"), None);
+    }
+    #[test]
+    fn test_rename(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("a = 5; b := a + 1; f(x) = x + a; g(a) = a*2").unwrap();
+        engine.evaluate("rename a width").unwrap();
+        assert_eq!(engine.get_variable("a"), None);
+        assert_eq!(engine.get_variable("width"), number(5));
+        assert_eq!(engine.binding("b").unwrap().to_string(), "width + 1");
+        assert_eq!(engine.evaluate("width = 1; b; f(1); g(1)").unwrap()[1..], [Value::Number(Number::from(2)), Value::Number(Number::from(2)), Value::Number(Number::from(2))]);
+        assert!(engine.rename("b", "width").is_err());
+        assert!(engine.rename("b", "sin").is_err());
+        assert!(matches!(engine.rename("nothing", "z"), Err(CalcError::UndefinedVariable(_))));
+        // c would suddenly get a value from the renamed variable
+        engine.evaluate("c = z + 1").unwrap();
+        assert!(engine.rename("b", "z").is_err());
+    }
+    #[test]
+    fn test_reset(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("a = 5; f(x) = x").unwrap();
+        let mut format = NumberFormat::default();
+        format.fractions = false;
+        engine.set_number_format(format);
+        engine.evaluate("reset").unwrap();
+        assert!(engine.variables().is_empty());
+        assert!(engine.functions().is_empty());
+        assert!(engine.number_format().fractions);
+        assert_eq!(engine.history().len(), 1);
+        engine.undo().unwrap();
+        assert_eq!(engine.get_variable("a"), number(5));
+        engine.reset();
+        assert!(engine.history().is_empty());
+    }
+}
+#[cfg(test)]
+mod test_plot{
+    use calculator::{CalcError, CalculatorEngine, Value};
+    #[test]
+    fn test_graph_uses_the_session(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("a = 2; f(t) = t^2; x = 100").unwrap();
+        let graph = engine.graph("a * f(x)", "x").unwrap();
+        assert_eq!(graph.at(3.0), Some(18.0));
+        assert_eq!(graph.label, "a * f(x)");
+        assert!(matches!(engine.graph("x + y", "x"), Err(CalcError::InvalidExpression(_))));
+        assert!(engine.graph("x +", "x").is_err());
+    }
+    #[test]
+    fn test_sampling_leaves_gaps(){
+        let engine = CalculatorEngine::new();
+        let pieces = |input: &str, from: f64, to: f64| engine.graph(input, "x").unwrap().sample(from, to, 1000);
+        assert_eq!(pieces("sin(x)", -10.0, 10.0).len(), 1);
+        assert_eq!(pieces("x^3 - 50*x", -10.0, 10.0).len(), 1);
+        // 1/x has no value at 0 itself, tan jumps between samples at its poles
+        assert_eq!(pieces("1/x", -1.0, 1.0).len(), 2);
+        assert_eq!(pieces("tan(x)", -3.0, 3.0).len(), 3);
+        assert_eq!(pieces("floor(x)", 0.5, 3.5).len(), 4);
+        let root = pieces("sqrt(x)", -1.0, 1.0);
+        assert_eq!(root.len(), 1);
+        assert!(root[0].iter().all(|[x, _]| *x >= 0.0));
+    }
+    #[test]
+    fn test_parametric_and_polar(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("r = 2").unwrap();
+        let circle = engine.parametric("r*cos(t)", "r*sin(t)", "t").unwrap();
+        assert_eq!(circle.label, "(r*cos(t), r*sin(t))");
+        let pieces = circle.sample(0.0, std::f64::consts::TAU, 200);
+        assert_eq!(pieces.len(), 1);
+        assert!(pieces[0].iter().all(|[x, y]| (x.hypot(*y) - 2.0).abs() < 1e-9));
+        // the cardioid r = 1 + cos(theta) touches the origin at theta = pi
+        let cardioid = engine.polar("1 + cos(theta)", "theta").unwrap();
+        let [x, y] = cardioid.at(0.0).unwrap();
+        assert!((x - 2.0).abs() < 1e-12 && y.abs() < 1e-12);
+        let [x, y] = cardioid.at(std::f64::consts::PI).unwrap();
+        assert!(x.abs() < 1e-12 && y.abs() < 1e-12);
+        // a pole in one coordinate breaks the curve
+        assert_eq!(engine.parametric("t", "1/(t - 1)", "t").unwrap().sample(0.0, 2.0, 100).len(), 2);
+        assert!(engine.polar("theta + q", "theta").is_err());
+    }
+    #[test]
+    fn test_comparisons(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("a = 3").unwrap();
+        assert_eq!(engine.evaluate("a < 4; a >= 3; 2^10 > 1000; a <= 2").unwrap(), [Value::Bool(true), Value::Bool(true), Value::Bool(true), Value::Bool(false)]);
+        assert!(matches!(engine.evaluate("q < 1"), Err(CalcError::InvalidExpression(_))));
+        assert!(engine.evaluate("b = (1 < 2) + 1").is_err());
+        assert_eq!(calculator::parse("y<=2*x+1").unwrap().to_string(), "y <= 2*x + 1");
+    }
+    #[test]
+    fn test_implicit_curves(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("r = 5").unwrap();
+        let circle = engine.implicit("x^2 + y^2 == r^2").unwrap();
+        let lines = circle.contour([-10.0, -10.0], [10.0, 10.0], [80, 80]);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].first(), lines[0].last());
+        assert!(lines[0].iter().all(|[x, y]| (x.hypot(*y) - 5.0).abs() < 0.05));
+        assert!(!circle.holds(0.0, 0.0));
+        // no line is drawn along the pole at x = 0
+        let hyperbola = engine.implicit("y == 1/x").unwrap().contour([-4.0, -4.0], [4.0, 4.0], [81, 81]);
+        assert_eq!(hyperbola.len(), 2);
+        assert!(hyperbola.iter().flatten().all(|[x, y]| (y - 1.0 / x).abs() < 0.2));
+        assert!(engine.implicit("x + y").is_err());
+        assert!(engine.implicit("x < z").is_err());
+    }
+    #[test]
+    fn test_inequality_regions(){
+        let engine = CalculatorEngine::new();
+        let below = engine.implicit("y < 2*x + 1").unwrap();
+        assert!(below.holds(0.0, 0.0));
+        assert!(!below.holds(0.0, 2.0));
+        // one rectangle per row, from where the line crosses the row to the right edge
+        let rows = below.region([-5.0, -5.0], [5.0, 5.0], [100, 10]);
+        assert_eq!(rows.len(), 10);
+        assert!(rows.iter().all(|[low, high]| high[0] == 5.0 && (low[0] - ((low[1] + high[1]) / 2.0 - 1.0) / 2.0).abs() <= 0.1));
+    }
+}
+#[cfg(test)]
+mod test_table{
+    use calculator::{CalcError, CalculatorEngine, Number, TableRange, Value};
+    fn range(var: &str, start: i64, end: i64, step: i64) -> TableRange {
+        TableRange::new(var, Number::from(start), Number::from(end), Number::from(step))
+    }
+    #[test]
+    fn test_table_command(){
+        let mut engine = CalculatorEngine::new();
+        engine.evaluate("k = 3").unwrap();
+        let text = engine.evaluate("table(k*x^2, x, 0, 1, 0.5)").unwrap()[0].to_string();
+        assert_eq!(text, "x   | k*x^2\n----+------\n0   | 0\n1/2 | 3/4\n1   | 3");
+        let text = engine.evaluate("table(x*y, x, 1, 2, 1, y, 10, 20, 10)").unwrap()[0].to_string();
+        assert_eq!(text, "x \\ y | 10 | 20\n------+----+---\n1     | 10 | 20\n2     | 20 | 40");
+        assert!(engine.evaluate("table(x, x, 0, 1)").is_err());
+        assert!(engine.evaluate("table(x, x, 0, 1, 0)").is_err());
+        assert!(engine.evaluate("table(x, x, 0, 1000000, 1)").is_err());
+        assert!(engine.evaluate("2 * table(x, x, 0, 1, 1)").is_err());
+    }
+    #[test]
+    fn test_sort_and_csv(){
+        let engine = CalculatorEngine::new();
+        let expr = calculator::parse("1/(x - 1) + 1").unwrap();
+        let mut table = engine.table(&expr, &[range("x", -1, 2, 1)]).unwrap();
+        assert!(matches!(table.rows[2][1], Err(CalcError::DivisionByZero)));
+        // decimals even though the session shows fractions
+        assert!(engine.number_format().fractions);
+        assert_eq!(table.to_csv(), "x,1/(x - 1) + 1\n-1,0.5\n0,0\n1,\n2,2\n");
+        table.sort_by_column(1, true);
+        let column: Vec<String> = table.rows.iter().map(|row| row[0].as_ref().unwrap().to_string()).collect();
+        assert_eq!(column, ["2", "-1", "0", "1"]);
+        table.sort_by_column(1, false);
+        assert_eq!(table.rows[0][1].as_ref().unwrap(), &Value::Number(Number::from(0)));
+        // headers with a comma get quoted
+        let table = engine.table(&calculator::parse("max(x, 1)").unwrap(), &[range("x", 0, 0, 1)]).unwrap();
+        assert!(table.to_csv().starts_with("x,\"max(x, 1)\"\n"));
+    }
+}